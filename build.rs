@@ -0,0 +1,46 @@
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+// Compile the GLSL shaders in shaders/ to SPIR-V, the output ends up in OUT_DIR
+// and is included in the binary with include_bytes!. The .glsl files are
+// shared code pulled in with `#include "file.glsl"`.
+fn main() {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let mut compiler = shaderc::Compiler::new().expect("Failed to create shader compiler");
+    let mut options =
+        shaderc::CompileOptions::new().expect("Failed to create shader compile options");
+    options.set_include_callback(|name, _, _, _| {
+        let path = Path::new("shaders").join(name);
+        let content = read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: path.display().to_string(),
+            content,
+        })
+    });
+
+    println!("cargo:rerun-if-changed=shaders");
+    for entry in glob::glob("shaders/*").unwrap() {
+        let src_path = entry.unwrap();
+        println!("cargo:rerun-if-changed={}", src_path.display());
+        let kind = match src_path.extension().and_then(|ext| ext.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            Some("comp") => shaderc::ShaderKind::Compute,
+            _ => continue,
+        };
+
+        let src = read_to_string(&src_path)
+            .unwrap_or_else(|_| panic!("Failed to read {}", src_path.display()));
+        let file_name = src_path.file_name().unwrap().to_str().unwrap();
+        let compiled = compiler
+            .compile_into_spirv(&src, kind, file_name, "main", Some(&options))
+            .unwrap_or_else(|e| panic!("Failed to compile {}: {}", file_name, e));
+
+        write(
+            out_dir.join(format!("{}.spv", file_name)),
+            compiled.as_binary_u8(),
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform texture2D t_tex_b;
+layout(set=0, binding=3) uniform CompareUniforms {
+    uint mode;
+    // Canvas x-coordinate of the swipe divider
+    float divider;
+    float gain;
+    float opacity;
+    float checker_size;
+} compare;
+// The window of the image compared against.
+layout(set=0, binding=8) uniform CompareImageUniforms {
+    float window_low;
+    float window_width;
+    uint windowed;
+    uint float_source;
+} image_b;
+
+#include "display.glsl"
+
+const uint SWIPE = 0u;
+const uint DIFFERENCE = 1u;
+const uint CHECKERBOARD_COMPARE = 2u;
+const uint ONION_SKIN = 3u;
+
+// B is placed pixel for pixel over A from their top left corners, rather than
+// stretched to the size of A. Outside of B is transparent.
+vec4 sample_b() {
+    vec2 size_a = vec2(textureSize(sampler2D(t_tex, s_tex), 0));
+    vec2 size_b = vec2(textureSize(sampler2D(t_tex_b, s_tex), 0));
+    vec2 tex = v_tex * size_a / size_b;
+    if (any(greaterThan(tex, vec2(1.0)))) {
+        return vec4(0.0);
+    }
+    return texture(sampler2D(t_tex_b, s_tex), tex);
+}
+
+void main() {
+    vec4 a = display_color(apply_window(texture(sampler2D(t_tex, s_tex), v_tex)));
+    vec4 b = sample_b();
+    b = display_color(map_window(b, image_b.window_low, image_b.window_width, image_b.windowed));
+
+    if (compare.mode == SWIPE) {
+        f_color = gl_FragCoord.x < compare.divider ? a : b;
+        // Draw the divider line
+        if (abs(gl_FragCoord.x - compare.divider) < 1.0) {
+            f_color = vec4(1.0);
+        }
+    } else if (compare.mode == DIFFERENCE) {
+        f_color = vec4(clamp(abs(a.rgb - b.rgb) * compare.gain, 0.0, 1.0), 1.0);
+    } else if (compare.mode == CHECKERBOARD_COMPARE) {
+        ivec2 cell = ivec2(floor(gl_FragCoord.xy / compare.checker_size));
+        f_color = ((cell.x + cell.y) & 1) == 0 ? a : b;
+    } else {
+        f_color = mix(a, b, compare.opacity);
+    }
+}
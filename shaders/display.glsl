@@ -0,0 +1,78 @@
+// The display settings and the window of the image, included by the shaders
+// that draw images.
+
+#include "srgb.glsl"
+
+layout(set=0, binding=4) uniform DisplayUniforms {
+    // Screen pixels per image pixel needed to show the grid and the values
+    float grid_min_scale;
+    float values_min_scale;
+    uint show_grid;
+    uint show_values;
+    uint interpolation;
+    uint channel;
+    uint alpha_mode;
+    // In screen pixels
+    float checker_size;
+    // Linear
+    vec4 background;
+};
+layout(set=0, binding=7) uniform ImageUniforms {
+    float window_low;
+    float window_width;
+    uint windowed;
+    // Set for textures that aren't 8-bit
+    uint float_source;
+};
+
+// Must match Channel::shader_mode
+const uint ALL_CHANNELS = 0u;
+const uint RED = 1u;
+const uint GREEN = 2u;
+const uint BLUE = 3u;
+const uint ALPHA = 4u;
+const uint LUMINANCE = 5u;
+
+// Must match AlphaMode::shader_mode
+const uint OPAQUE = 0u;
+const uint CHECKERBOARD = 1u;
+const uint BACKGROUND = 2u;
+
+// Raw values are mapped through the window to display values, which the
+// output pass encodes as sRGB, so they are decoded to linear here.
+vec4 map_window(vec4 color, float low, float width, uint is_windowed) {
+    if (is_windowed != 0u) {
+        color.rgb = srgb_to_linear(clamp((color.rgb - low) / width, 0.0, 1.0));
+    }
+    return color;
+}
+
+// Through the window of the image.
+vec4 apply_window(vec4 color) {
+    return map_window(color, window_low, window_width, windowed);
+}
+
+// The selected channel of a display color, composited over the background.
+vec4 display_color(vec4 color) {
+    if (channel == ALPHA) {
+        return vec4(vec3(color.a), 1.0);
+    } else if (channel == RED) {
+        color.rgb = vec3(color.r);
+    } else if (channel == GREEN) {
+        color.rgb = vec3(color.g);
+    } else if (channel == BLUE) {
+        color.rgb = vec3(color.b);
+    } else if (channel == LUMINANCE) {
+        color.rgb = vec3(dot(color.rgb, vec3(0.2126, 0.7152, 0.0722)));
+    }
+    if (alpha_mode == OPAQUE) {
+        return vec4(color.rgb, 1.0);
+    }
+    vec3 behind = background.rgb;
+    if (alpha_mode == CHECKERBOARD) {
+        ivec2 cell = ivec2(floor(gl_FragCoord.xy / checker_size));
+        // sRGB 0.8 and 0.6
+        behind = ((cell.x + cell.y) & 1) == 0 ? vec3(0.604) : vec3(0.319);
+    }
+    return vec4(mix(behind, color.rgb, color.a), 1.0);
+}
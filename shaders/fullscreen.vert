@@ -0,0 +1,12 @@
+#version 450
+
+// A triangle covering the whole viewport, for the filter passes. No vertex
+// buffer is needed, the position is derived from the vertex index.
+
+layout(location=0) out vec2 v_tex;
+
+void main() {
+    vec2 pos = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2));
+    v_tex = pos;
+    gl_Position = vec4(pos.x * 2.0 - 1.0, 1.0 - pos.y * 2.0, 0.0, 1.0);
+}
@@ -0,0 +1,41 @@
+#version 450
+
+// One direction of a separable Gaussian blur. The input is clamped at the
+// edges, src/convolution.rs has the CPU version of the same filter.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform FilterUniforms {
+    vec2 size;
+    vec2 texel;
+    // x: sigma in pixels, y: 0 for horizontal, 1 for vertical
+    vec4 params[4];
+};
+
+const int MAX_RADIUS = 32;
+
+vec4 fetch(ivec2 p) {
+    return texelFetch(sampler2D(t_tex, s_tex), clamp(p, ivec2(0), ivec2(size) - 1), 0);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float sigma = params[0].x;
+    if (sigma <= 0.0) {
+        f_color = fetch(pixel);
+        return;
+    }
+    ivec2 dir = params[0].y < 0.5 ? ivec2(1, 0) : ivec2(0, 1);
+    int radius = min(int(ceil(3.0 * sigma)), MAX_RADIUS);
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int i = -radius; i <= radius; i++) {
+        float w = exp(-float(i * i) / (2.0 * sigma * sigma));
+        sum += w * fetch(pixel + i * dir);
+        total += w;
+    }
+    f_color = sum / total;
+}
@@ -0,0 +1,79 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// Overlay drawn on top of the image at high magnification: lines between
+// the image pixels and the pixel values as digits.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+
+#include "display.glsl"
+
+// 3x5 bitmaps of the digits, row by row from the top with 3 bits per row.
+const int DIGITS[10] = int[10](
+    0x7B6F, 0x2C97, 0x73E7, 0x73CF, 0x5BC9,
+    0x79CF, 0x79EF, 0x7249, 0x7BEF, 0x7BCF
+);
+
+bool digit_pixel(int digit, ivec2 p) {
+    int bit = 14 - (p.y * 3 + p.x);
+    return ((DIGITS[digit] >> bit) & 1) == 1;
+}
+
+// Is the glyph cell g (in font units) inside the digits of the value?
+// Three rows of three digits, one row per color channel.
+bool value_pixel(vec4 value, ivec2 g) {
+    if (g.x < 0 || g.y < 0 || g.x >= 11 || g.y >= 17) {
+        return false;
+    }
+    ivec2 in_glyph = ivec2(g.x % 4, g.y % 6);
+    if (in_glyph.x >= 3 || in_glyph.y >= 5) {
+        return false;
+    }
+    int channel = g.y / 6;
+    int v = int(round(clamp(value[channel], 0.0, 1.0) * 255.0));
+    int col = g.x / 4;
+    int divisor = col == 0 ? 100 : (col == 1 ? 10 : 1);
+    // Skip leading zeros
+    if (v < divisor && col < 2) {
+        return false;
+    }
+    return digit_pixel((v / divisor) % 10, in_glyph);
+}
+
+void main() {
+    vec2 size = vec2(textureSize(sampler2D(t_tex, s_tex), 0));
+    vec2 texel = v_tex * size;
+    // Screen pixels per image pixel
+    vec2 scale = 1.0 / fwidth(texel);
+    float pixels = min(scale.x, scale.y);
+
+    f_color = vec4(0.0);
+
+    // Only 8-bit values fit in the digits, float and raw values aren't shown.
+    if (show_values != 0u && float_source == 0u && pixels >= values_min_scale) {
+        vec4 value = texelFetch(sampler2D(t_tex, s_tex), ivec2(texel), 0);
+        // The texture decodes sRGB, the values are shown as stored.
+        value.rgb = linear_to_srgb(value.rgb);
+        float unit = max(1.0, floor(pixels / 20.0));
+        vec2 origin = (vec2(pixels) - vec2(11.0, 17.0) * unit) / 2.0;
+        ivec2 g = ivec2(floor((fract(texel) * scale - origin) / unit));
+        if (value_pixel(value, g)) {
+            float luminance = dot(value.rgb, vec3(0.2126, 0.7152, 0.0722));
+            f_color = luminance > 0.5 ? vec4(0.0, 0.0, 0.0, 1.0) : vec4(1.0);
+        }
+    }
+
+    if (show_grid != 0u && pixels >= grid_min_scale) {
+        // Distance to the closest pixel edge in screen pixels
+        vec2 edge = min(fract(texel), 1.0 - fract(texel)) * scale;
+        if (min(edge.x, edge.y) < 0.5) {
+            // Fade the grid in above the threshold
+            float alpha = clamp((pixels - grid_min_scale) / grid_min_scale, 0.2, 0.6);
+            f_color = vec4(0.5, 0.5, 0.5, alpha);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// Draws the image, resampled with the selected interpolation, showing the
+// selected channels.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+
+#include "display.glsl"
+
+// Must match Interpolation::shader_mode
+const uint NEAREST = 0u;
+const uint LINEAR = 1u;
+const uint BICUBIC = 2u;
+const uint CATMULL_ROM = 3u;
+const uint LANCZOS3 = 4u;
+
+const float PI = 3.14159265;
+
+vec4 fetch(ivec2 p, ivec2 size) {
+    return texelFetch(sampler2D(t_tex, s_tex), clamp(p, ivec2(0), size - 1), 0);
+}
+
+// Mitchell-Netravali cubic, B = 1, C = 0 is the B-spline and B = 0, C = 0.5 Catmull-Rom.
+float cubic(float x, float b, float c) {
+    x = abs(x);
+    if (x < 1.0) {
+        return ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)) / 6.0;
+    } else if (x < 2.0) {
+        return ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)) / 6.0;
+    }
+    return 0.0;
+}
+
+float lanczos3(float x) {
+    x = abs(x);
+    if (x < 1e-5) {
+        return 1.0;
+    }
+    if (x >= 3.0) {
+        return 0.0;
+    }
+    float px = PI * x;
+    return 3.0 * sin(px) * sin(px / 3.0) / (px * px);
+}
+
+float kernel(float x) {
+    if (interpolation == BICUBIC) {
+        return cubic(x, 1.0, 0.0);
+    } else if (interpolation == CATMULL_ROM) {
+        return cubic(x, 0.0, 0.5);
+    }
+    return lanczos3(x);
+}
+
+// Separable filter over the 2 * radius texels closest to the sample point.
+vec4 resample(int radius) {
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), 0);
+    // Relative to the texel centers
+    vec2 texel = v_tex * vec2(size) - 0.5;
+    ivec2 base = ivec2(floor(texel));
+    vec2 f = texel - vec2(base);
+
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int j = 1 - radius; j <= radius; j++) {
+        float wy = kernel(float(j) - f.y);
+        for (int i = 1 - radius; i <= radius; i++) {
+            float w = kernel(float(i) - f.x) * wy;
+            sum += w * fetch(base + ivec2(i, j), size);
+            total += w;
+        }
+    }
+    vec4 color = sum / total;
+    // Ringing is clamped to the range of 8-bit sources, float sources can be
+    // brighter than 1 and raw values are clamped by the window.
+    if (float_source == 0u && windowed == 0u) {
+        return clamp(color, 0.0, 1.0);
+    }
+    color.a = clamp(color.a, 0.0, 1.0);
+    return color;
+}
+
+void main() {
+    vec4 color;
+    if (interpolation == NEAREST || interpolation == LINEAR) {
+        // Filtered by the sampler in the bind group
+        color = texture(sampler2D(t_tex, s_tex), v_tex);
+    } else if (interpolation == LANCZOS3) {
+        color = resample(3);
+    } else {
+        color = resample(2);
+    }
+    f_color = display_color(apply_window(color));
+}
@@ -0,0 +1,18 @@
+#version 450
+
+// Transforms the shared unit quad to the position of the image on screen.
+
+layout(location=0) in vec3 a_pos;
+layout(location=1) in vec2 a_tex;
+layout(location=0) out vec2 v_tex;
+
+layout(set=1, binding=0) uniform TransformUniforms {
+    mat4 unit_to_clip;
+    // Part of the texture covered by the image
+    vec2 tex_scale;
+};
+
+void main() {
+    v_tex = a_tex * tex_scale;
+    gl_Position = unit_to_clip * vec4(a_pos, 1.0);
+}
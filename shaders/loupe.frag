@@ -0,0 +1,30 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// The magnified image inside a circle around the cursor.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=6) uniform LoupeUniforms {
+    // In screen pixels
+    vec2 center;
+    float radius;
+    float border;
+};
+
+#include "display.glsl"
+
+void main() {
+    float dist = distance(gl_FragCoord.xy, center);
+    if (dist > radius) {
+        discard;
+    }
+    if (dist > radius - border) {
+        f_color = vec4(1.0, 1.0, 1.0, 1.0);
+    } else {
+        f_color = display_color(apply_window(texture(sampler2D(t_tex, s_tex), v_tex)));
+    }
+}
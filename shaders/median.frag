@@ -0,0 +1,48 @@
+#version 450
+
+// Median of each channel in a 3x3 or 5x5 window.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform FilterUniforms {
+    vec2 size;
+    vec2 texel;
+    // x: radius, 1 or 2
+    vec4 params[4];
+};
+
+vec4 fetch(ivec2 p) {
+    return texelFetch(sampler2D(t_tex, s_tex), clamp(p, ivec2(0), ivec2(size) - 1), 0);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    int radius = clamp(int(params[0].x + 0.5), 1, 2);
+    vec4 values[25];
+    int n = 0;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            values[n] = fetch(pixel + ivec2(x, y));
+            n++;
+        }
+    }
+    vec4 result;
+    for (int c = 0; c < 4; c++) {
+        // Insertion sort, the window is small.
+        float sorted[25];
+        for (int i = 0; i < n; i++) {
+            float v = values[i][c];
+            int j = i;
+            while (j > 0 && sorted[j - 1] > v) {
+                sorted[j] = sorted[j - 1];
+                j--;
+            }
+            sorted[j] = v;
+        }
+        result[c] = sorted[n / 2];
+    }
+    f_color = result;
+}
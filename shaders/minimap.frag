@@ -0,0 +1,37 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// Thumbnail of the whole image with the visible region outlined, the rest
+// of the image is dimmed.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=5) uniform MinimapUniforms {
+    // left, top, right, bottom in texture coordinates
+    vec4 region;
+    // Width of the outline in screen pixels
+    float border;
+};
+
+#include "display.glsl"
+
+void main() {
+    vec4 color = display_color(apply_window(texture(sampler2D(t_tex, s_tex), v_tex)));
+    // Screen pixels per texture coordinate unit
+    vec2 scale = 1.0 / fwidth(v_tex);
+
+    // Signed distance to the outline in screen pixels, negative inside the region
+    vec2 outside = max(region.xy - v_tex, v_tex - region.zw) * scale;
+    float dist = max(outside.x, outside.y);
+
+    if (abs(dist) < border) {
+        f_color = vec4(1.0, 1.0, 1.0, 1.0);
+    } else if (dist > 0.0) {
+        f_color = vec4(color.rgb * 0.4, 1.0);
+    } else {
+        f_color = vec4(color.rgb, 1.0);
+    }
+}
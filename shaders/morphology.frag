@@ -0,0 +1,36 @@
+#version 450
+
+// Erosion (minimum) or dilation (maximum) of each channel over a square
+// window. Opening and closing are two passes of this shader.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform FilterUniforms {
+    vec2 size;
+    vec2 texel;
+    // x: radius, y: 0 to erode, 1 to dilate
+    vec4 params[4];
+};
+
+const int MAX_RADIUS = 16;
+
+vec4 fetch(ivec2 p) {
+    return texelFetch(sampler2D(t_tex, s_tex), clamp(p, ivec2(0), ivec2(size) - 1), 0);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    int radius = clamp(int(params[0].x + 0.5), 0, MAX_RADIUS);
+    bool dilate = params[0].y > 0.5;
+    vec4 result = fetch(pixel);
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            vec4 v = fetch(pixel + ivec2(x, y));
+            result = dilate ? max(result, v) : min(result, v);
+        }
+    }
+    f_color = result;
+}
@@ -0,0 +1,46 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// The last pass, from the linear scene to the render target: exposure,
+// tone mapping, compositing over the background and sRGB encoding.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform DisplayTransform {
+    float gain;
+    // Set when the target doesn't encode to sRGB itself
+    uint encode_srgb;
+    uint tone_map;
+    // Linear, not affected by the exposure and tone mapping
+    vec4 background;
+};
+
+const uint CLAMP = 0u;
+const uint REINHARD = 1u;
+const uint ACES = 2u;
+
+// Narkowicz's fit of the ACES filmic tone curve.
+vec3 aces(vec3 x) {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+#include "srgb.glsl"
+
+void main() {
+    vec4 color = texelFetch(sampler2D(t_tex, s_tex), ivec2(gl_FragCoord.xy), 0);
+    vec3 rgb = max(color.rgb * gain, 0.0);
+    if (tone_map == REINHARD) {
+        rgb = rgb / (1.0 + rgb);
+    } else if (tone_map == ACES) {
+        rgb = aces(rgb);
+    }
+    // The scene is premultiplied, with nothing drawn around the images.
+    rgb = clamp(rgb, 0.0, 1.0) + background.rgb * (1.0 - clamp(color.a, 0.0, 1.0));
+    if (encode_srgb != 0u) {
+        rgb = linear_to_srgb(rgb);
+    }
+    f_color = vec4(rgb, 1.0);
+}
@@ -0,0 +1,128 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// Converts a headerless raw buffer to RGBA, drawn over an image texture of
+// the same size. The buffer is uploaded byte by byte in rows of ROW_BYTES
+// texels. 8-bit formats are written as linear values to an sRGB texture,
+// 16-bit formats as raw values to a float texture.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform utexture2D t_raw;
+layout(set=0, binding=1) uniform sampler s_raw;
+layout(set=0, binding=2) uniform RawUniforms {
+    uint format;
+    // The index in the 2x2 cell of the red sample, blue is diagonally opposite.
+    uint pattern;
+    uint big_endian;
+    // Bytes from one row to the next
+    uint stride;
+    uint width;
+    uint height;
+};
+
+#include "srgb.glsl"
+
+// Must match RAW_ROW_BYTES in raw.rs
+const uint ROW_BYTES = 8192u;
+
+// Must match PixelFormat::shader_mode
+const uint GRAY8 = 0u;
+const uint GRAY16 = 1u;
+const uint RGB8 = 2u;
+const uint RGBA8 = 3u;
+const uint BGRA8 = 4u;
+const uint BAYER8 = 5u;
+const uint BAYER16 = 6u;
+
+const uint RED = 0u;
+const uint GREEN = 1u;
+const uint BLUE = 2u;
+
+uint byte_at(uint offset) {
+    ivec2 p = ivec2(offset % ROW_BYTES, offset / ROW_BYTES);
+    return texelFetch(usampler2D(t_raw, s_raw), p, 0).r;
+}
+
+uint bytes_per_pixel() {
+    if (format == GRAY16 || format == BAYER16) {
+        return 2u;
+    } else if (format == RGB8) {
+        return 3u;
+    } else if (format == RGBA8 || format == BGRA8) {
+        return 4u;
+    }
+    return 1u;
+}
+
+// Sample i of the pixel at p, 8-bit samples in 0..1 and 16-bit as stored.
+float sample_at(ivec2 p, uint i) {
+    uint offset = uint(p.y) * stride + uint(p.x) * bytes_per_pixel();
+    if (format == GRAY16 || format == BAYER16) {
+        uint a = byte_at(offset);
+        uint b = byte_at(offset + 1u);
+        return float(big_endian != 0u ? (a << 8) | b : (b << 8) | a);
+    }
+    return float(byte_at(offset + i)) / 255.0;
+}
+
+// Out of range positions are mirrored, which keeps the color of the mosaic.
+float mosaic(ivec2 p) {
+    ivec2 last = ivec2(width, height) - 1;
+    p = abs(p);
+    p = min(p, 2 * last - p);
+    return sample_at(p, 0u);
+}
+
+uint color_at(ivec2 p) {
+    uint index = uint(p.x & 1) | (uint(p.y & 1) << 1);
+    if (index == pattern) {
+        return RED;
+    } else if (index == (pattern ^ 3u)) {
+        return BLUE;
+    }
+    return GREEN;
+}
+
+// Bilinear demosaicing: the missing colors are the average of the closest
+// samples of that color.
+vec3 demosaic(ivec2 p) {
+    float center = mosaic(p);
+    float across = (mosaic(p + ivec2(-1, 0)) + mosaic(p + ivec2(1, 0))
+        + mosaic(p + ivec2(0, -1)) + mosaic(p + ivec2(0, 1))) / 4.0;
+    float diagonal = (mosaic(p + ivec2(-1, -1)) + mosaic(p + ivec2(1, -1))
+        + mosaic(p + ivec2(-1, 1)) + mosaic(p + ivec2(1, 1))) / 4.0;
+    float horizontal = (mosaic(p + ivec2(-1, 0)) + mosaic(p + ivec2(1, 0))) / 2.0;
+    float vertical = (mosaic(p + ivec2(0, -1)) + mosaic(p + ivec2(0, 1))) / 2.0;
+
+    uint color = color_at(p);
+    if (color == RED) {
+        return vec3(center, across, diagonal);
+    } else if (color == BLUE) {
+        return vec3(diagonal, across, center);
+    } else if (color_at(p + ivec2(1, 0)) == RED) {
+        return vec3(horizontal, center, vertical);
+    }
+    return vec3(vertical, center, horizontal);
+}
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    vec4 color;
+    if (format == GRAY8 || format == GRAY16) {
+        color = vec4(vec3(sample_at(p, 0u)), 1.0);
+    } else if (format == RGB8) {
+        color = vec4(sample_at(p, 0u), sample_at(p, 1u), sample_at(p, 2u), 1.0);
+    } else if (format == RGBA8) {
+        color = vec4(sample_at(p, 0u), sample_at(p, 1u), sample_at(p, 2u), sample_at(p, 3u));
+    } else if (format == BGRA8) {
+        color = vec4(sample_at(p, 2u), sample_at(p, 1u), sample_at(p, 0u), sample_at(p, 3u));
+    } else {
+        color = vec4(demosaic(p), 1.0);
+    }
+    if (format != GRAY16 && format != BAYER16) {
+        color.rgb = srgb_to_linear(color.rgb);
+    }
+    f_color = color;
+}
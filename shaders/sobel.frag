@@ -0,0 +1,38 @@
+#version 450
+
+// Gradient magnitude of the luminance with the Sobel operator.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform FilterUniforms {
+    vec2 size;
+    vec2 texel;
+    // x: scale of the magnitude
+    vec4 params[4];
+};
+
+float luminance(ivec2 p) {
+    vec3 rgb = texelFetch(sampler2D(t_tex, s_tex), clamp(p, ivec2(0), ivec2(size) - 1), 0).rgb;
+    return dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+}
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    float tl = luminance(p + ivec2(-1, -1));
+    float t = luminance(p + ivec2(0, -1));
+    float tr = luminance(p + ivec2(1, -1));
+    float l = luminance(p + ivec2(-1, 0));
+    float r = luminance(p + ivec2(1, 0));
+    float bl = luminance(p + ivec2(-1, 1));
+    float b = luminance(p + ivec2(0, 1));
+    float br = luminance(p + ivec2(1, 1));
+    float gx = (tr + 2.0 * r + br) - (tl + 2.0 * l + bl);
+    float gy = (bl + 2.0 * b + br) - (tl + 2.0 * t + tr);
+    float magnitude = clamp(length(vec2(gx, gy)) * params[0].x, 0.0, 1.0);
+    // Premultiplied by the alpha of the pixel, like its input.
+    float alpha = texelFetch(sampler2D(t_tex, s_tex), p, 0).a;
+    f_color = vec4(vec3(magnitude), 1.0) * alpha;
+}
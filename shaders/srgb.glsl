@@ -0,0 +1,13 @@
+// The sRGB transfer function, included by the shaders that convert between
+// sRGB and linear values.
+
+vec3 srgb_to_linear(vec3 v) {
+    return mix(v / 12.92, pow((v + 0.055) / 1.055, vec3(2.4)), step(0.04045, v));
+}
+
+vec3 linear_to_srgb(vec3 v) {
+    v = clamp(v, 0.0, 1.0);
+    vec3 low = v * 12.92;
+    vec3 high = 1.055 * pow(v, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(v, vec3(0.0031308)));
+}
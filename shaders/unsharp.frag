@@ -0,0 +1,45 @@
+#version 450
+
+// Unsharp mask: the difference to a Gaussian blur is added back to the image.
+
+layout(location=0) in vec2 v_tex;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_tex;
+layout(set=0, binding=1) uniform sampler s_tex;
+layout(set=0, binding=2) uniform FilterUniforms {
+    vec2 size;
+    vec2 texel;
+    // x: sigma in pixels, y: amount
+    vec4 params[4];
+};
+
+// The blur is done in one pass, which limits the size of the kernel.
+const int MAX_RADIUS = 8;
+
+vec4 fetch(ivec2 p) {
+    return texelFetch(sampler2D(t_tex, s_tex), clamp(p, ivec2(0), ivec2(size) - 1), 0);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec4 original = fetch(pixel);
+    float sigma = params[0].x;
+    if (sigma <= 0.0) {
+        f_color = original;
+        return;
+    }
+    int radius = min(int(ceil(3.0 * sigma)), MAX_RADIUS);
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            float w = exp(-float(x * x + y * y) / (2.0 * sigma * sigma));
+            sum += w * fetch(pixel + ivec2(x, y));
+            total += w;
+        }
+    }
+    vec3 blurred = sum.rgb / total;
+    vec3 sharpened = original.rgb + params[0].y * (original.rgb - blurred);
+    f_color = vec4(clamp(sharpened, 0.0, 1.0), original.a);
+}
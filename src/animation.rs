@@ -0,0 +1,131 @@
+use crate::{vertex::Quad, view_state::SavedView};
+
+/// Duration of view transitions in milliseconds.
+pub const TRANSITION_DURATION: f64 = 300.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map the linear progress t in [0, 1] to the eased progress.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// A transition between two views. The start time is taken from the first
+/// frame that is rendered, times are in milliseconds.
+#[derive(Debug, Clone)]
+pub struct ViewAnimation {
+    from: SavedView,
+    to: SavedView,
+    start: Option<f64>,
+    duration: f64,
+    easing: Easing,
+}
+
+impl ViewAnimation {
+    pub fn new(from: SavedView, to: SavedView, duration: f64, easing: Easing) -> Self {
+        ViewAnimation {
+            from,
+            to,
+            start: None,
+            duration,
+            easing,
+        }
+    }
+
+    /// The view at the given time, and whether the animation is finished.
+    pub fn step(&mut self, quad: &Quad, now: f64) -> (SavedView, bool) {
+        let start = *self.start.get_or_insert(now);
+        let t = if self.duration > 0.0 {
+            ((now - start) / self.duration) as f32
+        } else {
+            1.0
+        };
+        if t >= 1.0 {
+            return (self.to, true);
+        }
+        (
+            interpolate(quad, &self.from, &self.to, self.easing.apply(t)),
+            false,
+        )
+    }
+
+    pub fn target(&self) -> &SavedView {
+        &self.to
+    }
+}
+
+/// Interpolate between two views, the scale is interpolated geometrically so
+/// that zooming appears to run at a constant speed.
+pub fn interpolate(quad: &Quad, from: &SavedView, to: &SavedView, t: f32) -> SavedView {
+    let from_scale = quad.zoom_scale(from.zoom).ln();
+    let to_scale = quad.zoom_scale(to.zoom).ln();
+    let scale = (from_scale + (to_scale - from_scale) * t).exp();
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    SavedView {
+        zoom: quad.zoom_with_scale(scale, to.zoom),
+        center: (
+            lerp(from.center.0, to.center.0),
+            lerp(from.center.1, to.center.1),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view_state::Zoom;
+
+    #[test]
+    fn easing_end_points() {
+        for easing in [Easing::Linear, Easing::EaseOut, Easing::EaseInOut].iter() {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert!((easing.apply(0.5) - 0.5).abs() <= 0.4);
+        }
+    }
+
+    #[test]
+    fn animation_reaches_target() {
+        let mut quad = Quad::new();
+        quad.set_viewport_size((100.0, 100.0));
+        quad.map_texture_coords((50.0, 50.0), (50.0, 50.0));
+        let from = SavedView {
+            zoom: Zoom::Fit(1.0),
+            center: (0.5, 0.5),
+        };
+        let to = SavedView {
+            zoom: Zoom::Pixel(8.0),
+            center: (0.25, 0.75),
+        };
+        let mut animation = ViewAnimation::new(from, to, 100.0, Easing::Linear);
+
+        let (view, done) = animation.step(&quad, 1000.0);
+        assert!((quad.zoom_scale(view.zoom) - 2.0).abs() < 1e-4);
+        assert_eq!(view.center, from.center);
+        assert!(!done);
+        // Half way in log-space between a scale of 2 and 8
+        let (view, done) = animation.step(&quad, 1050.0);
+        assert!((quad.zoom_scale(view.zoom) - 4.0).abs() < 1e-4);
+        assert_eq!(view.center, (0.375, 0.625));
+        assert!(!done);
+        assert_eq!(animation.step(&quad, 1100.0), (to, true));
+    }
+}
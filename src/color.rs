@@ -0,0 +1,342 @@
+/// How the stored values of an image map to linear light, in 0..1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transfer {
+    Srgb,
+    /// Linear light is the stored value raised to this power.
+    Gamma(f32),
+    /// ICC parametric curve: function type and the parameters g, a, b, c, d, e, f.
+    Parametric(u16, [f32; 7]),
+    /// Samples of the curve evenly spaced over 0..1, linearly interpolated.
+    Table(Vec<f32>),
+}
+
+impl Transfer {
+    pub fn to_linear(&self, v: f32) -> f32 {
+        match self {
+            Transfer::Srgb => srgb_to_linear(v),
+            Transfer::Gamma(gamma) => v.max(0.0).powf(*gamma),
+            Transfer::Parametric(kind, p) => {
+                let [g, a, b, c, d, e, f] = *p;
+                let curve = |x: f32| (a * x + b).max(0.0).powf(g);
+                match *kind {
+                    0 => v.max(0.0).powf(g),
+                    1 if v >= -b / a => curve(v),
+                    1 => 0.0,
+                    2 if v >= -b / a => curve(v) + c,
+                    2 => c,
+                    3 if v >= d => curve(v),
+                    3 => c * v,
+                    _ if v >= d => curve(v) + e,
+                    _ => c * v + f,
+                }
+            }
+            Transfer::Table(table) => match table.len() {
+                0 => v,
+                1 => table[0],
+                n => {
+                    let x = v.max(0.0).min(1.0) * (n - 1) as f32;
+                    let i = (x.floor() as usize).min(n - 2);
+                    let t = x - i as f32;
+                    table[i] * (1.0 - t) + table[i + 1] * t
+                }
+            },
+        }
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.max(0.0).min(1.0);
+    if v <= 0.003_130_8 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The transfer function of a PNG from its sRGB, iCCP or gAMA chunk, in that
+/// order of precedence as in the PNG specification. None if the file has none
+/// of them, or isn't a PNG. The primaries (cHRM, the matrix of ICC profiles)
+/// are ignored and taken to be those of sRGB.
+pub fn png_transfer(bytes: &[u8]) -> Option<Transfer> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut icc = None;
+    let mut gamma = None;
+    let mut pos = SIGNATURE.len();
+    // The color chunks come before the image data.
+    while pos + 8 <= bytes.len() {
+        let length =
+            u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+        let kind = &bytes[pos + 4..pos + 8];
+        // Files with a truncated chunk, or a length past the address space, are
+        // left alone.
+        let end = (pos + 8).checked_add(length as usize)?;
+        let data = bytes.get(pos + 8..end)?;
+        match kind {
+            b"sRGB" => return Some(Transfer::Srgb),
+            b"iCCP" => icc = Some(data),
+            b"gAMA" if data.len() == 4 => {
+                let value = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                if value > 0 {
+                    // The file gamma is the exponent from linear to stored values.
+                    gamma = Some(Transfer::Gamma(100_000.0 / value as f32));
+                }
+            }
+            b"IDAT" | b"IEND" => break,
+            _ => {}
+        }
+        // Data and CRC.
+        pos = end + 4;
+    }
+    icc.and_then(iccp_transfer).or(gamma)
+}
+
+// The profile of an iCCP chunk is zlib compressed after its name.
+fn iccp_transfer(data: &[u8]) -> Option<Transfer> {
+    let name_end = data.iter().position(|&b| b == 0)?;
+    let compressed = data.get(name_end + 2..)?;
+    let profile = miniz_oxide::inflate::decompress_to_vec_zlib(compressed).ok()?;
+    icc_transfer(&profile)
+}
+
+/// The tone curve of an RGB or gray ICC profile. The red curve is used for
+/// all channels of RGB profiles.
+pub fn icc_transfer(profile: &[u8]) -> Option<Transfer> {
+    let u32_at = |pos: usize| {
+        profile
+            .get(pos..pos + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let u16_at = |pos: usize| {
+        profile
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let wanted: &[u8] = match profile.get(16..20)? {
+        b"RGB " => b"rTRC",
+        b"GRAY" => b"kTRC",
+        _ => return None,
+    };
+    // The tag table can't run past the end of the profile.
+    let count = (u32_at(128)? as usize).min(profile.len().saturating_sub(132) / 12);
+    let offset = (0..count).find_map(|i| {
+        let entry = 132 + 12 * i;
+        if profile.get(entry..entry + 4)? == wanted {
+            u32_at(entry + 4)
+        } else {
+            None
+        }
+    })? as usize;
+
+    match profile.get(offset..offset + 4)? {
+        b"curv" => match u32_at(offset + 8)? {
+            0 => Some(Transfer::Gamma(1.0)),
+            // A u8Fixed8Number
+            1 => Some(Transfer::Gamma(u16_at(offset + 12)? as f32 / 256.0)),
+            n => (0..n as usize)
+                .map(|i| u16_at(offset + 12 + 2 * i).map(|v| v as f32 / 65535.0))
+                .collect::<Option<Vec<_>>>()
+                .map(Transfer::Table),
+        },
+        b"para" => {
+            let kind = u16_at(offset + 8)?;
+            let count = *[1, 3, 4, 5, 7].get(kind as usize)?;
+            let mut params = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+            for (i, param) in params.iter_mut().take(count).enumerate() {
+                // s15Fixed16Number
+                *param = u32_at(offset + 12 + 4 * i)? as i32 as f32 / 65536.0;
+            }
+            Some(Transfer::Parametric(kind, params))
+        }
+        _ => None,
+    }
+}
+
+/// Re-encode the colors of an image as sRGB, which is what the image textures
+/// store. Alpha is left as is.
+pub fn convert_to_srgb(image: &mut image::RgbaImage, transfer: &Transfer) {
+    if *transfer == Transfer::Srgb {
+        return;
+    }
+    let mut table = [0u8; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        let linear = transfer.to_linear(i as f32 / 255.0);
+        *value = (linear_to_srgb(linear) * 255.0).round() as u8;
+    }
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = table[*channel as usize];
+        }
+    }
+}
+
+pub fn is_srgb(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Bgra8UnormSrgb
+    )
+}
+
+/// How values above 1 are brought into the range of the display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    Clamp,
+    /// x / (1 + x)
+    Reinhard,
+    /// Fit of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMap {
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            ToneMap::Clamp => 0,
+            ToneMap::Reinhard => 1,
+            ToneMap::Aces => 2,
+        }
+    }
+}
+
+impl std::str::FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!("Unknown tone mapping: {}", s)),
+        }
+    }
+}
+
+/// The mapping from the linear scene to the values shown on the display.
+#[derive(Debug, Clone)]
+pub struct DisplayTransform {
+    /// In stops, 0 shows the image as is.
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    /// Linear color the scene is composited over, it isn't tone mapped.
+    pub background: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DisplayTransformUniforms {
+    gain: f32,
+    /// Set for targets that don't encode to sRGB themselves.
+    encode_srgb: u32,
+    tone_map: u32,
+    _padding: u32,
+    background: [f32; 4],
+}
+unsafe impl bytemuck::Pod for DisplayTransformUniforms {}
+unsafe impl bytemuck::Zeroable for DisplayTransformUniforms {}
+
+impl DisplayTransform {
+    pub fn new() -> Self {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            background: [0.0; 3],
+        }
+    }
+
+    pub fn uniforms(&self, target_format: wgpu::TextureFormat) -> DisplayTransformUniforms {
+        let [r, g, b] = self.background;
+        DisplayTransformUniforms {
+            gain: self.exposure.exp2(),
+            encode_srgb: (!is_srgb(target_format)) as u32,
+            tone_map: self.tone_map.shader_mode(),
+            _padding: 0,
+            background: [r, g, b, 1.0],
+        }
+    }
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // The CRC isn't checked.
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    #[test]
+    fn png_chunks() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(b"IHDR", &[0; 13]));
+        png.extend(chunk(b"gAMA", &45_455u32.to_be_bytes()));
+        let mut with_srgb = png.clone();
+        png.extend(chunk(b"IDAT", &[]));
+        match png_transfer(&png) {
+            Some(Transfer::Gamma(gamma)) => assert!((gamma - 2.2).abs() < 1e-3),
+            other => panic!("Unexpected transfer: {:?}", other),
+        }
+
+        with_srgb.extend(chunk(b"sRGB", &[0]));
+        assert_eq!(png_transfer(&with_srgb), Some(Transfer::Srgb));
+        let mut truncated = with_srgb[..33].to_vec();
+        truncated.extend_from_slice(&u32::MAX.to_be_bytes());
+        truncated.extend_from_slice(b"tEXt");
+        assert_eq!(png_transfer(&truncated), None);
+        assert_eq!(png_transfer(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn icc_curves() {
+        let mut profile = vec![0u8; 132];
+        profile[16..20].copy_from_slice(b"GRAY");
+        profile[128..132].copy_from_slice(&1u32.to_be_bytes());
+        profile.extend_from_slice(b"kTRC");
+        profile.extend_from_slice(&144u32.to_be_bytes());
+        profile.extend_from_slice(&14u32.to_be_bytes());
+        profile.extend_from_slice(b"curv\0\0\0\0");
+        profile.extend_from_slice(&1u32.to_be_bytes());
+        profile.extend_from_slice(&[0x01, 0xcd]);
+        assert_eq!(icc_transfer(&profile), Some(Transfer::Gamma(1.80078125)));
+
+        // A tag count past the end of the profile stops at its end.
+        profile[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+        profile[132..136].copy_from_slice(b"rTRC");
+        assert_eq!(icc_transfer(&profile), None);
+
+        // The sRGB curve as an ICC parametric curve.
+        let srgb = Transfer::Parametric(
+            3,
+            [
+                2.4,
+                1.0 / 1.055,
+                0.055 / 1.055,
+                1.0 / 12.92,
+                0.04045,
+                0.0,
+                0.0,
+            ],
+        );
+        for &v in &[0.0, 0.02, 0.5, 1.0] {
+            assert!((srgb.to_linear(v) - srgb_to_linear(v)).abs() < 1e-6);
+        }
+    }
+}
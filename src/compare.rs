@@ -0,0 +1,127 @@
+/// Ways of comparing two images in a single viewport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareMode {
+    /// Image A left of a draggable divider, image B to the right.
+    Swipe,
+    /// Absolute difference |A - B| amplified by a gain.
+    Difference,
+    /// A and B interleaved in a checkerboard pattern.
+    Checkerboard,
+    /// B blended on top of A.
+    OnionSkin,
+}
+
+impl CompareMode {
+    // Must match the constants in shaders/compare.frag
+    fn shader_mode(&self) -> u32 {
+        match self {
+            CompareMode::Swipe => 0,
+            CompareMode::Difference => 1,
+            CompareMode::Checkerboard => 2,
+            CompareMode::OnionSkin => 3,
+        }
+    }
+
+    pub fn next(&self) -> Option<CompareMode> {
+        match self {
+            CompareMode::Swipe => Some(CompareMode::Difference),
+            CompareMode::Difference => Some(CompareMode::Checkerboard),
+            CompareMode::Checkerboard => Some(CompareMode::OnionSkin),
+            CompareMode::OnionSkin => None,
+        }
+    }
+}
+
+impl std::str::FromStr for CompareMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "swipe" => Ok(CompareMode::Swipe),
+            "difference" => Ok(CompareMode::Difference),
+            "checkerboard" => Ok(CompareMode::Checkerboard),
+            "onion_skin" => Ok(CompareMode::OnionSkin),
+            _ => Err(format!("Unknown compare mode: {}", s)),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct CompareUniforms {
+    mode: u32,
+    divider: f32,
+    gain: f32,
+    opacity: f32,
+    checker_size: f32,
+    _padding: [f32; 3],
+}
+unsafe impl bytemuck::Pod for CompareUniforms {}
+unsafe impl bytemuck::Zeroable for CompareUniforms {}
+
+// How close (in logical pixels) to the divider a drag must start to move it.
+const DIVIDER_GRAB_DISTANCE: f32 = 8.0;
+
+#[derive(Debug)]
+pub struct Compare {
+    pub mode: Option<CompareMode>,
+    /// The image slot compared against the image shown in the pane.
+    pub image: usize,
+    /// Canvas x-coordinate of the swipe divider, in physical pixels.
+    pub divider: f32,
+    pub gain: f32,
+    pub opacity: f32,
+    /// Size of the checkerboard cells in logical pixels.
+    pub checker_size: f32,
+    dragging_divider: bool,
+}
+
+impl Compare {
+    pub fn new() -> Self {
+        Compare {
+            mode: None,
+            image: 1,
+            divider: 0.0,
+            gain: 1.0,
+            opacity: 0.5,
+            checker_size: 32.0,
+            dragging_divider: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    /// Start moving the divider if the drag starts close to it.
+    pub fn grab_divider(&mut self, pos: (f32, f32), scale_factor: f32) -> bool {
+        self.dragging_divider = self.mode == Some(CompareMode::Swipe)
+            && (pos.0 - self.divider).abs() < DIVIDER_GRAB_DISTANCE * scale_factor;
+        self.dragging_divider
+    }
+
+    pub fn is_dragging_divider(&self) -> bool {
+        self.dragging_divider
+    }
+
+    pub fn release_divider(&mut self) {
+        self.dragging_divider = false;
+    }
+
+    pub fn uniforms(&self, scale_factor: f32) -> CompareUniforms {
+        CompareUniforms {
+            mode: self.mode.map_or(0, |mode| mode.shader_mode()),
+            divider: self.divider,
+            gain: self.gain,
+            opacity: self.opacity,
+            checker_size: (self.checker_size * scale_factor).max(1.0),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Compare::new()
+    }
+}
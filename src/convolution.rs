@@ -0,0 +1,369 @@
+use crate::shaders::FilterShader;
+
+/// The built-in filters, each runs as one or more passes of the filter chain.
+/// Sizes are given in pixels of the image and scaled by the zoom of the
+/// active pane:
+///
+/// - `GaussianBlur`: sigma
+/// - `UnsharpMask`: sigma, amount
+/// - `Sobel`: scale of the gradient magnitude
+/// - `Median`: radius, at most 2 screen pixels
+/// - `Open`, `Close`: radius of the square structuring element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinFilter {
+    GaussianBlur,
+    UnsharpMask,
+    Sobel,
+    Median,
+    /// Erosion followed by dilation, removes small bright details.
+    Open,
+    /// Dilation followed by erosion, removes small dark details.
+    Close,
+}
+
+impl std::str::FromStr for BuiltinFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gaussian_blur" => Ok(BuiltinFilter::GaussianBlur),
+            "unsharp_mask" => Ok(BuiltinFilter::UnsharpMask),
+            "sobel" => Ok(BuiltinFilter::Sobel),
+            "median" => Ok(BuiltinFilter::Median),
+            "open" => Ok(BuiltinFilter::Open),
+            "close" => Ok(BuiltinFilter::Close),
+            _ => Err(format!("Unknown filter: {}", s)),
+        }
+    }
+}
+
+const ERODE: f32 = 0.0;
+const DILATE: f32 = 1.0;
+
+impl BuiltinFilter {
+    /// The shader passes with their parameters, for a canvas showing
+    /// `image_scale` screen pixels per image pixel. The number of passes
+    /// doesn't depend on the parameters, so they can be changed in place.
+    pub fn passes(&self, params: &[f32], image_scale: f32) -> Vec<(FilterShader, Vec<f32>)> {
+        let param = |i: usize, default: f32| params.get(i).copied().unwrap_or(default);
+        let size = |i: usize| param(i, 1.0) * image_scale;
+        match self {
+            BuiltinFilter::GaussianBlur => {
+                let sigma = size(0);
+                vec![
+                    (FilterShader::Gaussian, vec![sigma, 0.0]),
+                    (FilterShader::Gaussian, vec![sigma, 1.0]),
+                ]
+            }
+            BuiltinFilter::UnsharpMask => {
+                vec![(FilterShader::UnsharpMask, vec![size(0), param(1, 1.0)])]
+            }
+            BuiltinFilter::Sobel => vec![(FilterShader::Sobel, vec![param(0, 1.0)])],
+            BuiltinFilter::Median => vec![(FilterShader::Median, vec![size(0)])],
+            BuiltinFilter::Open => {
+                let radius = size(0);
+                vec![
+                    (FilterShader::Morphology, vec![radius, ERODE]),
+                    (FilterShader::Morphology, vec![radius, DILATE]),
+                ]
+            }
+            BuiltinFilter::Close => {
+                let radius = size(0);
+                vec![
+                    (FilterShader::Morphology, vec![radius, DILATE]),
+                    (FilterShader::Morphology, vec![radius, ERODE]),
+                ]
+            }
+        }
+    }
+}
+
+/// CPU versions of the filter shaders, the GPU output is checked against them.
+#[cfg(test)]
+pub mod reference {
+    use super::BuiltinFilter;
+    use crate::color::srgb_to_linear;
+    use crate::shaders::FilterShader;
+
+    /// Linear RGBA samples in 0..1, row by row.
+    #[derive(Debug, Clone)]
+    pub struct FloatImage {
+        pub width: u32,
+        pub height: u32,
+        pub data: Vec<[f32; 4]>,
+    }
+
+    impl FloatImage {
+        /// Decode sRGB like the image textures do, alpha is linear.
+        pub fn from_rgba(image: &image::RgbaImage) -> Self {
+            FloatImage {
+                width: image.width(),
+                height: image.height(),
+                data: image
+                    .pixels()
+                    .map(|p| {
+                        let c = |i: usize| srgb_to_linear(p.0[i] as f32 / 255.0);
+                        [c(0), c(1), c(2), p.0[3] as f32 / 255.0]
+                    })
+                    .collect(),
+            }
+        }
+
+        /// The pixel at (x, y), clamped to the edges like the shaders do.
+        pub fn get(&self, x: i32, y: i32) -> [f32; 4] {
+            let x = x.max(0).min(self.width as i32 - 1) as u32;
+            let y = y.max(0).min(self.height as i32 - 1) as u32;
+            self.data[(y * self.width + x) as usize]
+        }
+
+        fn map(&self, f: impl Fn(i32, i32) -> [f32; 4]) -> Self {
+            let mut data = Vec::with_capacity(self.data.len());
+            for y in 0..self.height as i32 {
+                for x in 0..self.width as i32 {
+                    data.push(f(x, y));
+                }
+            }
+            FloatImage {
+                width: self.width,
+                height: self.height,
+                data,
+            }
+        }
+    }
+
+    /// Filter an image shown at its size.
+    pub fn apply(filter: BuiltinFilter, params: &[f32], image: &FloatImage) -> FloatImage {
+        filter
+            .passes(params, 1.0)
+            .iter()
+            .fold(image.clone(), |image, (shader, params)| {
+                apply_pass(*shader, params, &image)
+            })
+    }
+
+    fn apply_pass(shader: FilterShader, params: &[f32], image: &FloatImage) -> FloatImage {
+        match shader {
+            FilterShader::Gaussian => {
+                let dir = if params[1] < 0.5 { (1, 0) } else { (0, 1) };
+                gaussian(image, params[0], dir)
+            }
+            FilterShader::UnsharpMask => unsharp_mask(image, params[0], params[1]),
+            FilterShader::Sobel => sobel(image, params[0]),
+            FilterShader::Median => {
+                let radius = ((params[0] + 0.5) as i32).max(1).min(2);
+                median(image, radius)
+            }
+            FilterShader::Morphology => {
+                let radius = ((params[0] + 0.5) as i32).max(0).min(16);
+                morphology(image, radius, params[1] > 0.5)
+            }
+        }
+    }
+
+    fn weight(d2: i32, sigma: f32) -> f32 {
+        (-(d2 as f32) / (2.0 * sigma * sigma)).exp()
+    }
+
+    fn gaussian(image: &FloatImage, sigma: f32, dir: (i32, i32)) -> FloatImage {
+        if sigma <= 0.0 {
+            return image.clone();
+        }
+        let radius = ((3.0 * sigma).ceil() as i32).min(32);
+        image.map(|x, y| {
+            let mut sum = [0.0; 4];
+            let mut total = 0.0;
+            for i in -radius..=radius {
+                let w = weight(i * i, sigma);
+                let v = image.get(x + i * dir.0, y + i * dir.1);
+                for (s, v) in sum.iter_mut().zip(v.iter()) {
+                    *s += w * v;
+                }
+                total += w;
+            }
+            for s in sum.iter_mut() {
+                *s /= total;
+            }
+            sum
+        })
+    }
+
+    fn unsharp_mask(image: &FloatImage, sigma: f32, amount: f32) -> FloatImage {
+        if sigma <= 0.0 {
+            return image.clone();
+        }
+        let radius = ((3.0 * sigma).ceil() as i32).min(8);
+        image.map(|x, y| {
+            let mut sum = [0.0; 3];
+            let mut total = 0.0;
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let w = weight(dx * dx + dy * dy, sigma);
+                    let v = image.get(x + dx, y + dy);
+                    for (s, v) in sum.iter_mut().zip(v.iter()) {
+                        *s += w * v;
+                    }
+                    total += w;
+                }
+            }
+            // Alpha is kept as is.
+            let mut out = image.get(x, y);
+            for (o, s) in out.iter_mut().zip(sum.iter()) {
+                let blurred = s / total;
+                *o = (*o + amount * (*o - blurred)).max(0.0).min(1.0);
+            }
+            out
+        })
+    }
+
+    fn sobel(image: &FloatImage, scale: f32) -> FloatImage {
+        let lum = |x: i32, y: i32| {
+            let v = image.get(x, y);
+            0.2126 * v[0] + 0.7152 * v[1] + 0.0722 * v[2]
+        };
+        image.map(|x, y| {
+            let gx = (lum(x + 1, y - 1) + 2.0 * lum(x + 1, y) + lum(x + 1, y + 1))
+                - (lum(x - 1, y - 1) + 2.0 * lum(x - 1, y) + lum(x - 1, y + 1));
+            let gy = (lum(x - 1, y + 1) + 2.0 * lum(x, y + 1) + lum(x + 1, y + 1))
+                - (lum(x - 1, y - 1) + 2.0 * lum(x, y - 1) + lum(x + 1, y - 1));
+            let m = ((gx * gx + gy * gy).sqrt() * scale).max(0.0).min(1.0);
+            // Premultiplied by the alpha of the pixel, like its input.
+            let a = image.get(x, y)[3];
+            [m * a, m * a, m * a, a]
+        })
+    }
+
+    fn window(image: &FloatImage, x: i32, y: i32, radius: i32) -> Vec<[f32; 4]> {
+        let mut values = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                values.push(image.get(x + dx, y + dy));
+            }
+        }
+        values
+    }
+
+    fn median(image: &FloatImage, radius: i32) -> FloatImage {
+        image.map(|x, y| {
+            let values = window(image, x, y, radius);
+            let mut out = [0.0; 4];
+            for (c, o) in out.iter_mut().enumerate() {
+                let mut channel: Vec<f32> = values.iter().map(|v| v[c]).collect();
+                channel.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                *o = channel[channel.len() / 2];
+            }
+            out
+        })
+    }
+
+    fn morphology(image: &FloatImage, radius: i32, dilate: bool) -> FloatImage {
+        image.map(|x, y| {
+            window(image, x, y, radius)
+                .iter()
+                .fold(image.get(x, y), |mut acc, v| {
+                    for (a, v) in acc.iter_mut().zip(v.iter()) {
+                        *a = if dilate { a.max(*v) } else { a.min(*v) };
+                    }
+                    acc
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reference::{self, FloatImage};
+    use super::*;
+    use crate::color::linear_to_srgb;
+    use crate::display::Interpolation;
+    use crate::renderer::State;
+    use crate::view_state::Zoom;
+
+    // Rows of the readback buffer must be a multiple of 256 bytes.
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 32;
+
+    fn test_image() -> image::RgbaImage {
+        image::RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            let noise = ((x * 7919 + y * 104_729) % 97) as u8;
+            let square = if (12..20).contains(&x) && (8..24).contains(&y) {
+                120
+            } else {
+                0
+            };
+            image::Rgba([
+                (x * 4) as u8 / 2 + noise,
+                (y * 8) as u8 / 2 + square,
+                noise.wrapping_mul(3),
+                255,
+            ])
+        })
+    }
+
+    // The render target stores sRGB, the filters work on linear values.
+    fn encode_srgb(v: f32) -> u8 {
+        (linear_to_srgb(v) * 255.0).round() as u8
+    }
+
+    #[test]
+    fn median_and_open_remove_a_bright_pixel() {
+        let mut image = image::RgbaImage::from_pixel(5, 5, image::Rgba([10, 10, 10, 255]));
+        image.put_pixel(2, 2, image::Rgba([250, 250, 250, 255]));
+        let image = FloatImage::from_rgba(&image);
+        let (dark, bright) = (image.get(0, 0), image.get(2, 2));
+        for &filter in &[BuiltinFilter::Median, BuiltinFilter::Open] {
+            let filtered = reference::apply(filter, &[1.0], &image);
+            assert!(filtered.data.iter().all(|p| *p == dark));
+        }
+        let closed = reference::apply(BuiltinFilter::Close, &[1.0], &image);
+        assert_eq!(closed.get(2, 2), bright);
+    }
+
+    #[test]
+    fn gpu_matches_reference() {
+        let image = test_image();
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(image.clone())
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let mut state = match State::headless((WIDTH, HEIGHT)) {
+            Some(state) => state,
+            None => return,
+        };
+        state.load_image(0, &png).unwrap();
+        state.set_interpolation(Interpolation::Nearest);
+        state.set_zoom_mode(Zoom::Pixel(1.0), false);
+
+        let input = FloatImage::from_rgba(&image);
+        let cases: &[(BuiltinFilter, &[f32])] = &[
+            (BuiltinFilter::GaussianBlur, &[1.5]),
+            (BuiltinFilter::UnsharpMask, &[1.0, 0.8]),
+            (BuiltinFilter::Sobel, &[0.5]),
+            (BuiltinFilter::Median, &[1.0]),
+            (BuiltinFilter::Median, &[2.0]),
+            (BuiltinFilter::Open, &[1.0]),
+            (BuiltinFilter::Close, &[2.0]),
+        ];
+        for (filter, params) in cases {
+            state.clear_filters();
+            state.add_builtin_filter(*filter, params);
+            let pixels = state.render_pixels();
+
+            let expected = reference::apply(*filter, params, &input);
+            for (i, (gpu, cpu)) in pixels.chunks(4).zip(expected.data.iter()).enumerate() {
+                for (c, (&gpu, &cpu)) in gpu.iter().zip(cpu.iter()).take(3).enumerate() {
+                    let expected = encode_srgb(cpu);
+                    assert!(
+                        (gpu as i32 - expected as i32).abs() <= 2,
+                        "{:?} at ({}, {}) channel {}: {} != {}",
+                        filter,
+                        i as u32 % WIDTH,
+                        i as u32 / WIDTH,
+                        c,
+                        gpu,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+}
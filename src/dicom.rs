@@ -0,0 +1,693 @@
+use crate::frames::{Frame, FrameSource};
+use crate::texture::{FloatImage, Pixels, WindowLevel};
+
+const IMPLICIT_LITTLE: &str = "1.2.840.10008.1.2";
+const EXPLICIT_LITTLE: &str = "1.2.840.10008.1.2.1";
+const DEFLATED: &str = "1.2.840.10008.1.2.1.99";
+const EXPLICIT_BIG: &str = "1.2.840.10008.1.2.2";
+const RLE: &str = "1.2.840.10008.1.2.5";
+
+const TRANSFER_SYNTAX: (u16, u16) = (0x0002, 0x0010);
+const SAMPLES_PER_PIXEL: (u16, u16) = (0x0028, 0x0002);
+const PHOTOMETRIC: (u16, u16) = (0x0028, 0x0004);
+const PLANAR_CONFIGURATION: (u16, u16) = (0x0028, 0x0006);
+const NUMBER_OF_FRAMES: (u16, u16) = (0x0028, 0x0008);
+const ROWS: (u16, u16) = (0x0028, 0x0010);
+const COLUMNS: (u16, u16) = (0x0028, 0x0011);
+const PIXEL_SPACING: (u16, u16) = (0x0028, 0x0030);
+const IMAGER_PIXEL_SPACING: (u16, u16) = (0x0018, 0x1164);
+const BITS_ALLOCATED: (u16, u16) = (0x0028, 0x0100);
+const BITS_STORED: (u16, u16) = (0x0028, 0x0101);
+const PIXEL_REPRESENTATION: (u16, u16) = (0x0028, 0x0103);
+const WINDOW_CENTER: (u16, u16) = (0x0028, 0x1050);
+const WINDOW_WIDTH: (u16, u16) = (0x0028, 0x1051);
+const RESCALE_INTERCEPT: (u16, u16) = (0x0028, 0x1052);
+const RESCALE_SLOPE: (u16, u16) = (0x0028, 0x1053);
+const PIXEL_DATA: (u16, u16) = (0x7fe0, 0x0010);
+const ITEM: (u16, u16) = (0xfffe, 0xe000);
+const ITEM_DELIMITER: (u16, u16) = (0xfffe, 0xe00d);
+const SEQUENCE_DELIMITER: (u16, u16) = (0xfffe, 0xe0dd);
+
+/// Whether the bytes are a DICOM file, with the preamble and "DICM" prefix.
+pub fn is_dicom(bytes: &[u8]) -> bool {
+    bytes.get(128..132) == Some(&b"DICM"[..])
+}
+
+// Reads the elements of a data set in one transfer syntax.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    explicit: bool,
+    big_endian: bool,
+}
+
+struct Element<'a> {
+    tag: (u16, u16),
+    /// None for undefined length, i.e. sequences and encapsulated pixel data.
+    value: Option<&'a [u8]>,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + length)
+            .ok_or_else(|| "Truncated DICOM file".to_string())?;
+        self.pos += length;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn peek_group(&self) -> Option<u16> {
+        let b = self.data.get(self.pos..self.pos + 2)?;
+        Some(if self.big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    }
+
+    // The tag and length of the next element, the value is read by the caller.
+    fn header(&mut self) -> Result<((u16, u16), Option<u32>), String> {
+        let tag = (self.u16()?, self.u16()?);
+        // Items and delimiters have no VR in any transfer syntax.
+        let length = if !self.explicit || tag.0 == 0xfffe {
+            self.u32()?
+        } else {
+            match self.bytes(2)? {
+                b"OB" | b"OD" | b"OF" | b"OL" | b"OV" | b"OW" | b"SQ" | b"SV" | b"UC" | b"UN"
+                | b"UR" | b"UT" | b"UV" => {
+                    self.bytes(2)?;
+                    self.u32()?
+                }
+                _ => self.u16()? as u32,
+            }
+        };
+        Ok((tag, Some(length).filter(|&length| length != 0xffff_ffff)))
+    }
+
+    fn element(&mut self) -> Result<Element<'a>, String> {
+        let (tag, length) = self.header()?;
+        let value = match length {
+            Some(length) => Some(self.bytes(length as usize)?),
+            None => None,
+        };
+        Ok(Element { tag, value })
+    }
+
+    // Skip the contents of a sequence or item of undefined length, up to and
+    // including its delimiter.
+    fn skip_undefined(&mut self) -> Result<(), String> {
+        loop {
+            let element = self.element()?;
+            if element.tag == ITEM_DELIMITER || element.tag == SEQUENCE_DELIMITER {
+                return Ok(());
+            }
+            if element.value.is_none() {
+                self.skip_undefined()?;
+            }
+        }
+    }
+
+    // Offset and length of the fragments of encapsulated pixel data, without
+    // the offset table.
+    fn fragments(&mut self) -> Result<Vec<(usize, usize)>, String> {
+        let mut fragments = Vec::new();
+        loop {
+            let element = self.element()?;
+            match (element.tag, element.value) {
+                (SEQUENCE_DELIMITER, _) => break,
+                (ITEM, Some(value)) => fragments.push((self.pos - value.len(), value.len())),
+                _ => return Err("Invalid encapsulated pixel data".to_string()),
+            }
+        }
+        if fragments.is_empty() {
+            return Err("Invalid encapsulated pixel data".to_string());
+        }
+        fragments.remove(0);
+        Ok(fragments)
+    }
+}
+
+fn u16_value(value: &[u8], big_endian: bool) -> Option<u16> {
+    let b = value.get(0..2)?;
+    Some(if big_endian {
+        u16::from_be_bytes([b[0], b[1]])
+    } else {
+        u16::from_le_bytes([b[0], b[1]])
+    })
+}
+
+fn text(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+// The numbers of a multi-valued decimal or integer string.
+fn numbers(value: &[u8]) -> Vec<f32> {
+    text(value)
+        .split('\\')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Photometric {
+    /// The lowest value is white.
+    Monochrome1,
+    Monochrome2,
+    Rgb,
+    YbrFull,
+}
+
+// Where the pixel data of the frames is.
+enum PixelData {
+    /// Offset and length in the data set.
+    Native(usize, usize),
+    /// Offset and length of one fragment per frame.
+    Rle(Vec<(usize, usize)>),
+}
+
+/// The frames of a DICOM file with uncompressed, deflated or RLE pixel data.
+/// Monochrome frames keep the stored values after the rescale slope and
+/// intercept, e.g. Hounsfield units, and are shown through the window of the
+/// file or the range of the values.
+pub struct DicomFrames {
+    data: Vec<u8>,
+    big_endian: bool,
+    pixel_data: PixelData,
+    frames: usize,
+    rows: u32,
+    columns: u32,
+    samples_per_pixel: usize,
+    planar: bool,
+    photometric: Photometric,
+    bits_allocated: u32,
+    bits_stored: u32,
+    signed: bool,
+    slope: f32,
+    intercept: f32,
+    window: Option<WindowLevel>,
+    pixel_spacing: Option<(f32, f32)>,
+}
+
+impl DicomFrames {
+    pub fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        if !is_dicom(&bytes) {
+            return Err("Not a DICOM file".to_string());
+        }
+        // The file meta information is always explicit VR little endian.
+        let mut meta = Reader {
+            data: &bytes,
+            pos: 132,
+            explicit: true,
+            big_endian: false,
+        };
+        let mut syntax = String::new();
+        while meta.peek_group() == Some(0x0002) {
+            let element = meta.element()?;
+            if element.tag == TRANSFER_SYNTAX {
+                syntax = text(element.value.unwrap_or_default());
+            }
+        }
+        let data = match syntax.as_str() {
+            DEFLATED => miniz_oxide::inflate::decompress_to_vec(&bytes[meta.pos..])
+                .map_err(|_| "Failed to inflate DICOM data set".to_string())?,
+            _ => bytes[meta.pos..].to_vec(),
+        };
+        let (explicit, big_endian) = match syntax.as_str() {
+            IMPLICIT_LITTLE => (false, false),
+            EXPLICIT_LITTLE | DEFLATED | RLE => (true, false),
+            EXPLICIT_BIG => (true, true),
+            _ => return Err(format!("Unsupported DICOM transfer syntax: {}", syntax)),
+        };
+        Self::parse(data, explicit, big_endian, syntax == RLE)
+    }
+
+    fn parse(data: Vec<u8>, explicit: bool, big_endian: bool, rle: bool) -> Result<Self, String> {
+        let mut reader = Reader {
+            data: &data,
+            pos: 0,
+            explicit,
+            big_endian,
+        };
+        let mut us = Vec::new();
+        let mut strings = Vec::new();
+        let mut pixel_data = None;
+        while !reader.is_done() {
+            let element = reader.element()?;
+            match (element.tag, element.value) {
+                (PIXEL_DATA, Some(value)) => {
+                    pixel_data = Some(PixelData::Native(reader.pos - value.len(), value.len()))
+                }
+                (PIXEL_DATA, None) => pixel_data = Some(PixelData::Rle(reader.fragments()?)),
+                (_, None) => reader.skip_undefined()?,
+                (
+                    SAMPLES_PER_PIXEL | PLANAR_CONFIGURATION | ROWS | COLUMNS | BITS_ALLOCATED
+                    | BITS_STORED | PIXEL_REPRESENTATION,
+                    Some(value),
+                ) => us.extend(u16_value(value, big_endian).map(|v| (element.tag, v))),
+                (tag, Some(value)) if tag.0 == 0x0028 || tag == IMAGER_PIXEL_SPACING => {
+                    strings.push((tag, value))
+                }
+                _ => {}
+            }
+        }
+        let get = |tag| us.iter().find(|(t, _)| *t == tag).map(|&(_, v)| v);
+        let string = |tag| strings.iter().find(|(t, _)| *t == tag).map(|&(_, v)| v);
+        let number = |tag| string(tag).and_then(|v| numbers(v).first().copied());
+
+        let rows = get(ROWS).ok_or("DICOM file without rows")? as u32;
+        let columns = get(COLUMNS).ok_or("DICOM file without columns")? as u32;
+        if rows == 0 || columns == 0 {
+            return Err(format!("Invalid DICOM image size: {}x{}", columns, rows));
+        }
+        let samples_per_pixel = get(SAMPLES_PER_PIXEL).unwrap_or(1) as usize;
+        let bits_allocated = get(BITS_ALLOCATED).unwrap_or(16) as u32;
+        let bits_stored = get(BITS_STORED).map_or(bits_allocated, u32::from);
+        let photometric = match text(string(PHOTOMETRIC).unwrap_or(b"MONOCHROME2")).as_str() {
+            "MONOCHROME1" => Photometric::Monochrome1,
+            "MONOCHROME2" => Photometric::Monochrome2,
+            "RGB" => Photometric::Rgb,
+            "YBR_FULL" => Photometric::YbrFull,
+            other => return Err(format!("Unsupported photometric interpretation: {}", other)),
+        };
+        let expected = match photometric {
+            Photometric::Monochrome1 | Photometric::Monochrome2 => 1,
+            _ => 3,
+        };
+        if samples_per_pixel != expected {
+            return Err(format!(
+                "Unsupported samples per pixel: {}",
+                samples_per_pixel
+            ));
+        }
+        if ![8, 16, 32].contains(&bits_allocated)
+            || bits_stored == 0
+            || bits_stored > bits_allocated
+        {
+            return Err(format!("Unsupported bits allocated: {}", bits_allocated));
+        }
+        let frames = number(NUMBER_OF_FRAMES).map_or(1, |n| n.max(1.0) as usize);
+        let pixel_data = pixel_data.ok_or("DICOM file without pixel data")?;
+        match &pixel_data {
+            PixelData::Rle(fragments) if !rle || fragments.len() != frames => {
+                return Err("Unsupported encapsulated pixel data".to_string())
+            }
+            _ => {}
+        }
+
+        // The first of the windows, the VOI LUT maps center - 0.5 - (width - 1) / 2
+        // to black and center - 0.5 + (width - 1) / 2 to white.
+        let window = match (number(WINDOW_CENTER), number(WINDOW_WIDTH)) {
+            (Some(center), Some(width)) if width >= 1.0 => Some(WindowLevel {
+                center: center - 0.5,
+                width: (width - 1.0).max(f32::EPSILON),
+            }),
+            _ => None,
+        };
+        // Row spacing, then column spacing.
+        let pixel_spacing = string(PIXEL_SPACING)
+            .or_else(|| string(IMAGER_PIXEL_SPACING))
+            .map(numbers)
+            .and_then(|spacing| match spacing[..] {
+                [row, column] if row > 0.0 && column > 0.0 => Some((column, row)),
+                _ => None,
+            });
+        Ok(DicomFrames {
+            big_endian,
+            pixel_data,
+            frames,
+            rows,
+            columns,
+            samples_per_pixel,
+            planar: get(PLANAR_CONFIGURATION) == Some(1),
+            photometric,
+            bits_allocated,
+            bits_stored,
+            signed: get(PIXEL_REPRESENTATION) == Some(1),
+            slope: number(RESCALE_SLOPE).unwrap_or(1.0),
+            intercept: number(RESCALE_INTERCEPT).unwrap_or(0.0),
+            window,
+            pixel_spacing,
+            data,
+        })
+    }
+
+    /// Width and height of the pixels in millimeters.
+    pub fn pixel_spacing(&self) -> Option<(f32, f32)> {
+        self.pixel_spacing
+    }
+
+    fn frame_length(&self) -> usize {
+        let bytes = (self.bits_allocated / 8) as usize;
+        (self.rows * self.columns) as usize * self.samples_per_pixel * bytes
+    }
+
+    // The samples of a frame, interleaved by pixel.
+    fn samples(&self, index: usize) -> Result<Vec<f32>, String> {
+        let length = self.frame_length();
+        let bytes = (self.bits_allocated / 8) as usize;
+        let (raw, big_endian, planar) = match &self.pixel_data {
+            PixelData::Native(offset, total) => {
+                let start = offset + index * length;
+                if (index + 1) * length > *total {
+                    return Err("Truncated DICOM pixel data".to_string());
+                }
+                (
+                    self.data[start..start + length].to_vec(),
+                    self.big_endian,
+                    self.planar,
+                )
+            }
+            PixelData::Rle(fragments) => {
+                let (offset, fragment_length) = fragments[index];
+                let fragment = &self.data[offset..offset + fragment_length];
+                let raw = decode_rle(fragment, length, self.samples_per_pixel, bytes)?;
+                (raw, false, false)
+            }
+        };
+        let count = (self.rows * self.columns) as usize;
+        let shift = 32 - self.bits_stored;
+        let samples = (0..count * self.samples_per_pixel).map(|i| {
+            let (pixel, sample) = (i / self.samples_per_pixel, i % self.samples_per_pixel);
+            let at = if planar { sample * count + pixel } else { i };
+            let b = &raw[at * bytes..(at + 1) * bytes];
+            let value = match (bytes, big_endian) {
+                (1, _) => b[0] as u32,
+                (2, false) => u16::from_le_bytes([b[0], b[1]]) as u32,
+                (2, true) => u16::from_be_bytes([b[0], b[1]]) as u32,
+                (_, false) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                (_, true) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+            };
+            // Only the low bits stored are used, sign extended if signed.
+            let value = value << shift;
+            if self.signed {
+                ((value as i32) >> shift) as f32
+            } else {
+                (value >> shift) as f32
+            }
+        });
+        Ok(samples.collect())
+    }
+}
+
+impl FrameSource for DicomFrames {
+    fn frame_count(&self) -> usize {
+        self.frames
+    }
+
+    fn frame(&mut self, index: usize) -> Result<Frame, String> {
+        if index >= self.frames {
+            return Err(format!(
+                "No frame {} in DICOM of {} frames",
+                index, self.frames
+            ));
+        }
+        let samples = self.samples(index)?;
+        let pixels: Vec<[f32; 4]> = match self.photometric {
+            Photometric::Monochrome1 | Photometric::Monochrome2 => samples
+                .iter()
+                .map(|&v| {
+                    let v = v * self.slope + self.intercept;
+                    [v, v, v, 1.0]
+                })
+                .collect(),
+            Photometric::Rgb => samples
+                .chunks_exact(3)
+                .map(|s| [s[0], s[1], s[2], 1.0])
+                .collect(),
+            Photometric::YbrFull => {
+                let half = (1u32 << (self.bits_stored - 1)) as f32;
+                samples
+                    .chunks_exact(3)
+                    .map(|s| {
+                        let (y, cb, cr) = (s[0], s[1] - half, s[2] - half);
+                        [
+                            y + 1.402 * cr,
+                            y - 0.344_136 * cb - 0.714_136 * cr,
+                            y + 1.772 * cb,
+                            1.0,
+                        ]
+                    })
+                    .collect()
+            }
+        };
+        let image = FloatImage {
+            width: self.columns,
+            height: self.rows,
+            pixels,
+        };
+        let window = match self.photometric {
+            Photometric::Monochrome1 | Photometric::Monochrome2 => {
+                self.window.or_else(|| WindowLevel::of_image(&image))
+            }
+            // Color is shown over the full range of the stored values.
+            _ => Some(WindowLevel::from_range(
+                0.0,
+                ((1u64 << self.bits_stored) - 1) as f32,
+            )),
+        };
+        // The lowest values are white.
+        let window = match (window, self.photometric) {
+            (Some(window), Photometric::Monochrome1) => Some(WindowLevel {
+                width: -window.width,
+                ..window
+            }),
+            _ => window,
+        };
+        Ok(Frame {
+            pixels: Pixels::Float(image),
+            window,
+            pixel_spacing: self.pixel_spacing,
+        })
+    }
+}
+
+// An RLE compressed frame, returned with the samples interleaved by pixel in
+// little endian. Each segment holds one byte of one sample of all pixels, the
+// most significant byte first.
+fn decode_rle(
+    fragment: &[u8],
+    length: usize,
+    samples_per_pixel: usize,
+    bytes: usize,
+) -> Result<Vec<u8>, String> {
+    let header = |i: usize| {
+        fragment
+            .get(4 * i..4 * i + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| "Truncated RLE header".to_string())
+    };
+    let segments = header(0)?;
+    if segments != samples_per_pixel * bytes || segments > 15 {
+        return Err(format!("Unexpected number of RLE segments: {}", segments));
+    }
+    let pixels = length / (samples_per_pixel * bytes);
+    let mut raw = vec![0u8; length];
+    for segment in 0..segments {
+        let start = header(segment + 1)?;
+        let end = if segment + 1 < segments {
+            header(segment + 2)?
+        } else {
+            fragment.len()
+        };
+        let data = fragment
+            .get(start..end)
+            .ok_or_else(|| "Invalid RLE segment offset".to_string())?;
+        let decoded = unpack_bits(data, pixels);
+        let (sample, byte) = (segment / bytes, segment % bytes);
+        for (pixel, &value) in decoded.iter().enumerate() {
+            raw[(pixel * samples_per_pixel + sample) * bytes + (bytes - 1 - byte)] = value;
+        }
+    }
+    Ok(raw)
+}
+
+// PackBits: n < 128 copies the next n + 1 bytes, n > 128 repeats the next
+// byte 257 - n times, 128 is skipped.
+fn unpack_bits(data: &[u8], length: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(length);
+    let mut i = 0;
+    while i < data.len() && out.len() < length {
+        let n = data[i] as usize;
+        i += 1;
+        if n < 128 {
+            let end = (i + n + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        } else if n > 128 {
+            if let Some(&value) = data.get(i) {
+                out.resize(out.len() + 257 - n, value);
+            }
+            i += 1;
+        }
+    }
+    out.resize(length, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(tag: (u16, u16), vr: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut bytes = tag.0.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&tag.1.to_le_bytes());
+        bytes.extend_from_slice(vr);
+        if vr == b"OW" || vr == b"OB" || vr == b"SQ" {
+            bytes.extend_from_slice(&[0, 0]);
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        } else {
+            bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        }
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    // Explicit VR little endian CT with two signed 2x1 frames.
+    fn ct(syntax: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 128];
+        bytes.extend_from_slice(b"DICM");
+        bytes.extend(element(TRANSFER_SYNTAX, b"UI", syntax));
+        // A sequence of undefined length with an item is skipped.
+        let mut sequence = element((0x0008, 0x1115), b"SQ", &[]);
+        let len = sequence.len();
+        sequence[len - 4..].copy_from_slice(&[0xff; 4]);
+        bytes.extend(sequence);
+        bytes.extend_from_slice(&[0xfe, 0xff, 0x00, 0xe0, 0xff, 0xff, 0xff, 0xff]);
+        bytes.extend(element((0x0008, 0x1150), b"UI", b"1.2\0"));
+        bytes.extend_from_slice(&[0xfe, 0xff, 0x0d, 0xe0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0xfe, 0xff, 0xdd, 0xe0, 0, 0, 0, 0]);
+        bytes.extend(element(IMAGER_PIXEL_SPACING, b"DS", b"0.3\\0.3 "));
+        bytes.extend(element(SAMPLES_PER_PIXEL, b"US", &1u16.to_le_bytes()));
+        bytes.extend(element(PHOTOMETRIC, b"CS", b"MONOCHROME2 "));
+        bytes.extend(element(NUMBER_OF_FRAMES, b"IS", b"2 "));
+        bytes.extend(element(ROWS, b"US", &1u16.to_le_bytes()));
+        bytes.extend(element(COLUMNS, b"US", &2u16.to_le_bytes()));
+        bytes.extend(element(PIXEL_SPACING, b"DS", b"0.5\\0.25"));
+        bytes.extend(element(BITS_ALLOCATED, b"US", &16u16.to_le_bytes()));
+        bytes.extend(element(BITS_STORED, b"US", &12u16.to_le_bytes()));
+        bytes.extend(element(PIXEL_REPRESENTATION, b"US", &1u16.to_le_bytes()));
+        bytes.extend(element(WINDOW_CENTER, b"DS", b"40\\400 "));
+        bytes.extend(element(WINDOW_WIDTH, b"DS", b"401\\2000"));
+        bytes.extend(element(RESCALE_INTERCEPT, b"DS", b"-1024 "));
+        bytes.extend(element(RESCALE_SLOPE, b"DS", b"2 "));
+        bytes
+    }
+
+    fn gray(frame: &Frame) -> Vec<f32> {
+        match &frame.pixels {
+            Pixels::Float(image) => image.pixels.iter().map(|p| p[0]).collect(),
+            _ => panic!("Expected raw values"),
+        }
+    }
+
+    #[test]
+    fn native_frames_are_rescaled() {
+        let mut bytes = ct(b"1.2.840.10008.1.2.1\0");
+        // 12 bits stored, 0xfff is -1 and the high bits are ignored.
+        let pixels: Vec<u8> = [0u16, 0xffff, 512, 0x0800]
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        bytes.extend(element(PIXEL_DATA, b"OW", &pixels));
+
+        let mut frames = DicomFrames::new(bytes).unwrap();
+        assert_eq!(frames.frame_count(), 2);
+        assert_eq!(frames.pixel_spacing(), Some((0.25, 0.5)));
+        let first = frames.frame(0).unwrap();
+        assert_eq!(gray(&first), vec![-1024.0, -1026.0]);
+        assert_eq!(
+            first.window,
+            Some(WindowLevel {
+                center: 39.5,
+                width: 400.0
+            })
+        );
+        assert_eq!(gray(&frames.frame(1).unwrap()), vec![0.0, -5120.0]);
+        assert!(frames.frame(2).is_err());
+    }
+
+    #[test]
+    fn empty_images_are_rejected() {
+        let mut bytes = ct(b"1.2.840.10008.1.2.1\0");
+        let rows = element(ROWS, b"US", &1u16.to_le_bytes());
+        let start = bytes
+            .windows(rows.len())
+            .position(|w| w == &rows[..])
+            .unwrap();
+        let end = start + rows.len();
+        bytes[end - 2..end].copy_from_slice(&[0, 0]);
+        bytes.extend(element(PIXEL_DATA, b"OW", &[]));
+        assert!(DicomFrames::new(bytes).is_err());
+    }
+
+    #[test]
+    fn rle_frames() {
+        let mut bytes = ct(b"1.2.840.10008.1.2.5\0");
+        bytes.extend_from_slice(&[0xe0, 0x7f, 0x10, 0x00]);
+        bytes.extend_from_slice(b"OB\0\0");
+        bytes.extend_from_slice(&[0xff; 4]);
+        let item = |data: &[u8]| {
+            let mut item = vec![0xfe, 0xff, 0x00, 0xe0];
+            item.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            item.extend_from_slice(data);
+            item
+        };
+        // The empty offset table, then a fragment per frame.
+        bytes.extend(item(&[]));
+        let segments: [(&[u8], &[u8]); 2] = [
+            (&[1, 0x00, 0x02], &[0xff, 0x00]),
+            (&[0xff, 0x01], &[1, 7, 9]),
+        ];
+        for (high, low) in segments.iter() {
+            let mut fragment = vec![0u8; 64];
+            fragment[0..4].copy_from_slice(&2u32.to_le_bytes());
+            fragment[4..8].copy_from_slice(&64u32.to_le_bytes());
+            fragment[8..12].copy_from_slice(&(64 + high.len() as u32).to_le_bytes());
+            fragment.extend_from_slice(high);
+            fragment.extend_from_slice(low);
+            bytes.extend(item(&fragment));
+        }
+        bytes.extend_from_slice(&[0xfe, 0xff, 0xdd, 0xe0, 0, 0, 0, 0]);
+
+        let mut frames = DicomFrames::new(bytes).unwrap();
+        // 0x0000 and 0x0200, then 0x0107 and 0x0109.
+        assert_eq!(gray(&frames.frame(0).unwrap()), vec![-1024.0, 0.0]);
+        assert_eq!(
+            gray(&frames.frame(1).unwrap()),
+            vec![-1024.0 + 526.0, -1024.0 + 530.0]
+        );
+    }
+
+    #[test]
+    fn packbits() {
+        assert_eq!(
+            unpack_bits(&[2, 1, 2, 3, 0xfe, 9, 0x80], 6),
+            vec![1, 2, 3, 9, 9, 9]
+        );
+    }
+}
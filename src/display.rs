@@ -0,0 +1,218 @@
+use crate::color::srgb_to_linear;
+
+/// How the image is resampled when it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Nearest,
+    /// Bilinear, switching to nearest at high magnification.
+    Linear,
+    /// Cubic B-spline, smooth without overshoot.
+    Bicubic,
+    /// Sharper cubic that passes through the pixel values.
+    CatmullRom,
+    /// Windowed sinc with 3 lobes.
+    Lanczos3,
+}
+
+impl Interpolation {
+    // Must match the constants in shaders/image.frag
+    fn shader_mode(&self) -> u32 {
+        match self {
+            Interpolation::Nearest => 0,
+            Interpolation::Linear => 1,
+            Interpolation::Bicubic => 2,
+            Interpolation::CatmullRom => 3,
+            Interpolation::Lanczos3 => 4,
+        }
+    }
+}
+
+impl std::str::FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Interpolation::Nearest),
+            "linear" => Ok(Interpolation::Linear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            "catmull_rom" => Ok(Interpolation::CatmullRom),
+            "lanczos3" => Ok(Interpolation::Lanczos3),
+            _ => Err(format!("Unknown interpolation: {}", s)),
+        }
+    }
+}
+
+/// The channels of the image that are shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    /// The color image.
+    All,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Rec. 709 luminance of the linear color.
+    Luminance,
+}
+
+impl Channel {
+    // Must match the constants in shaders/image.frag
+    fn shader_mode(&self) -> u32 {
+        match self {
+            Channel::All => 0,
+            Channel::Red => 1,
+            Channel::Green => 2,
+            Channel::Blue => 3,
+            Channel::Alpha => 4,
+            Channel::Luminance => 5,
+        }
+    }
+}
+
+impl std::str::FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Channel::All),
+            "red" => Ok(Channel::Red),
+            "green" => Ok(Channel::Green),
+            "blue" => Ok(Channel::Blue),
+            "alpha" => Ok(Channel::Alpha),
+            "luminance" => Ok(Channel::Luminance),
+            _ => Err(format!("Unknown channel: {}", s)),
+        }
+    }
+}
+
+/// How transparent pixels are shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Composited over a checkerboard.
+    Checkerboard,
+    /// Composited over the background color.
+    Background,
+}
+
+impl AlphaMode {
+    // Must match the constants in shaders/image.frag
+    fn shader_mode(&self) -> u32 {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Checkerboard => 1,
+            AlphaMode::Background => 2,
+        }
+    }
+}
+
+impl std::str::FromStr for AlphaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opaque" => Ok(AlphaMode::Opaque),
+            "checkerboard" => Ok(AlphaMode::Checkerboard),
+            "background" => Ok(AlphaMode::Background),
+            _ => Err(format!("Unknown alpha mode: {}", s)),
+        }
+    }
+}
+
+/// Settings for how the image is displayed. The magnification thresholds are
+/// in screen pixels per image pixel.
+#[derive(Debug, Clone)]
+pub struct DisplaySettings {
+    pub interpolation: Interpolation,
+    /// Sample with nearest neighbor instead of linear interpolation from this magnification.
+    pub nearest_min_scale: f32,
+    pub show_grid: bool,
+    pub grid_min_scale: f32,
+    pub show_values: bool,
+    pub values_min_scale: f32,
+    pub channel: Channel,
+    pub alpha: AlphaMode,
+    /// Size of the checkerboard squares in logical pixels.
+    pub checker_size: f32,
+    /// sRGB color around the image and behind transparent pixels.
+    pub background: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DisplayUniforms {
+    grid_min_scale: f32,
+    values_min_scale: f32,
+    show_grid: u32,
+    show_values: u32,
+    interpolation: u32,
+    channel: u32,
+    alpha_mode: u32,
+    /// In physical pixels.
+    checker_size: f32,
+    /// Linear, alpha is unused.
+    background: [f32; 4],
+}
+unsafe impl bytemuck::Pod for DisplayUniforms {}
+unsafe impl bytemuck::Zeroable for DisplayUniforms {}
+
+impl DisplaySettings {
+    pub fn new() -> Self {
+        DisplaySettings {
+            interpolation: Interpolation::Linear,
+            nearest_min_scale: 4.0,
+            show_grid: true,
+            grid_min_scale: 8.0,
+            show_values: false,
+            values_min_scale: 48.0,
+            channel: Channel::All,
+            alpha: AlphaMode::Opaque,
+            checker_size: 8.0,
+            background: [0.0; 3],
+        }
+    }
+
+    /// The background in linear light.
+    pub fn background_color(&self) -> [f32; 3] {
+        let [r, g, b] = self.background;
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)]
+    }
+
+    /// Should the image be sampled with the nearest neighbor sampler? The
+    /// higher quality modes filter in the shader and ignore the sampler.
+    pub fn use_nearest(&self, scale: f32) -> bool {
+        match self.interpolation {
+            Interpolation::Nearest => true,
+            Interpolation::Linear => scale >= self.nearest_min_scale,
+            _ => false,
+        }
+    }
+
+    /// Is there any overlay to draw at the magnification?
+    pub fn has_overlay(&self, scale: f32) -> bool {
+        (self.show_grid && scale >= self.grid_min_scale)
+            || (self.show_values && scale >= self.values_min_scale)
+    }
+
+    pub fn uniforms(&self, scale_factor: f32) -> DisplayUniforms {
+        let [r, g, b] = self.background_color();
+        DisplayUniforms {
+            grid_min_scale: self.grid_min_scale,
+            values_min_scale: self.values_min_scale,
+            show_grid: self.show_grid as u32,
+            show_values: self.show_values as u32,
+            interpolation: self.interpolation.shader_mode(),
+            channel: self.channel.shader_mode(),
+            alpha_mode: self.alpha.shader_mode(),
+            checker_size: (self.checker_size * scale_factor).max(1.0),
+            background: [r, g, b, 1.0],
+        }
+    }
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings::new()
+    }
+}
@@ -0,0 +1,718 @@
+use crate::color::{DisplayTransform, DisplayTransformUniforms};
+use crate::convolution::BuiltinFilter;
+use crate::shaders::{
+    create_shader_module, fullscreen_vertex_shader, output_fragment_shader, parse_wgsl,
+    write_spirv, FilterShader,
+};
+use std::collections::HashMap;
+use std::mem;
+
+/// The format the scene is drawn in, linear light with room for values above 1.
+pub const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Number of parameters a filter can have.
+pub const MAX_FILTER_PARAMS: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FilterUniforms {
+    /// Size of the input in pixels.
+    size: [f32; 2],
+    /// Size of one input pixel in texture coordinates.
+    texel: [f32; 2],
+    params: [f32; MAX_FILTER_PARAMS],
+}
+unsafe impl bytemuck::Pod for FilterUniforms {}
+unsafe impl bytemuck::Zeroable for FilterUniforms {}
+
+impl FilterUniforms {
+    fn new(size: (u32, u32), params: &[f32]) -> Self {
+        let mut uniforms = FilterUniforms {
+            size: [size.0 as f32, size.1 as f32],
+            texel: [1.0 / size.0.max(1) as f32, 1.0 / size.1.max(1) as f32],
+            params: [0.0; MAX_FILTER_PARAMS],
+        };
+        let count = params.len().min(MAX_FILTER_PARAMS);
+        uniforms.params[..count].copy_from_slice(&params[..count]);
+        uniforms
+    }
+}
+
+enum PassPipeline {
+    /// Compiled from user WGSL.
+    Custom(wgpu::RenderPipeline),
+    /// Shared by all passes with the shader.
+    Builtin(FilterShader),
+}
+
+struct Pass {
+    pipeline: PassPipeline,
+    buffer: wgpu::Buffer,
+    params: Vec<f32>,
+    // One per intermediate texture the pass can read from.
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+struct Filter {
+    // None for user filters, whose parameters go to their single pass as is.
+    builtin: Option<BuiltinFilter>,
+    params: Vec<f32>,
+    passes: Vec<Pass>,
+}
+
+impl Filter {
+    // The parameters of each pass.
+    fn pass_params(&self, image_scale: f32) -> Vec<Vec<f32>> {
+        match self.builtin {
+            Some(builtin) => builtin
+                .passes(&self.params, image_scale)
+                .into_iter()
+                .map(|(_, params)| params)
+                .collect(),
+            None => vec![self.params.clone()],
+        }
+    }
+}
+
+impl Pass {
+    fn write(&self, queue: &wgpu::Queue, size: (u32, u32)) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[FilterUniforms::new(size, &self.params)]),
+        );
+    }
+}
+
+// A render target that the passes read from and write to in turns.
+struct Intermediate {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// Post-processing passes over the rendered images. The images are drawn
+/// into an intermediate texture and each filter draws its input into the next
+/// texture. A filter can consist of several passes, e.g. the two directions of
+/// a separable blur. The overlays are drawn over the result of the filters,
+/// then the last pass applies the display transform, composites over the
+/// background and draws into the render target.
+pub struct FilterChain {
+    target_format: wgpu::TextureFormat,
+    size: (u32, u32),
+    // Screen pixels per image pixel, the built-in filters scale their sizes by it.
+    image_scale: f32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    vertex: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    builtin_pipelines: HashMap<FilterShader, wgpu::RenderPipeline>,
+    intermediates: Vec<Intermediate>,
+    filters: Vec<Filter>,
+    output_pipeline: wgpu::RenderPipeline,
+    output_buffer: wgpu::Buffer,
+    // One per intermediate texture, like the bind groups of the passes.
+    output_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl FilterChain {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("FilterBindGroupLayout"),
+            bindings: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: wgpu::CompareFunction::Undefined,
+            anisotropy_clamp: 1,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            label: Some("FilterSampler"),
+        });
+
+        let vertex = fullscreen_vertex_shader(device);
+        let output_pipeline = build_pipeline(
+            device,
+            &pipeline_layout,
+            &vertex,
+            &output_fragment_shader(device),
+            target_format,
+        );
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("DisplayTransformUniforms"),
+            size: mem::size_of::<DisplayTransformUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let mut chain = FilterChain {
+            target_format,
+            size,
+            image_scale: 1.0,
+            bind_group_layout,
+            pipeline_layout,
+            vertex,
+            sampler,
+            builtin_pipelines: HashMap::new(),
+            intermediates: Vec::new(),
+            filters: Vec::new(),
+            output_pipeline,
+            output_buffer,
+            output_bind_groups: Vec::new(),
+        };
+        chain.set_display_transform(queue, &DisplayTransform::new());
+        chain.create_intermediates(device);
+        chain
+    }
+
+    pub fn set_display_transform(&self, queue: &wgpu::Queue, transform: &DisplayTransform) {
+        queue.write_buffer(
+            &self.output_buffer,
+            0,
+            bytemuck::cast_slice(&[transform.uniforms(self.target_format)]),
+        );
+    }
+
+    /// Compile a WGSL fragment shader and append it to the chain, returns its
+    /// index. Compile and validation errors are returned as text.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &str,
+        params: &[f32],
+    ) -> Result<usize, String> {
+        if params.len() > MAX_FILTER_PARAMS {
+            return Err(format!(
+                "A filter can have at most {} parameters",
+                MAX_FILTER_PARAMS
+            ));
+        }
+        let module = device.create_shader_module(&compile_filter(source)?);
+        let pipeline = PassPipeline::Custom(self.build_pipeline(device, &module));
+        let pass = self.create_pass(device, queue, pipeline, params);
+        Ok(self.push(
+            device,
+            Filter {
+                builtin: None,
+                params: params.to_vec(),
+                passes: vec![pass],
+            },
+        ))
+    }
+
+    /// Append one of the built-in filters, returns its index.
+    pub fn add_builtin(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        filter: BuiltinFilter,
+        params: &[f32],
+    ) -> usize {
+        let passes = filter
+            .passes(params, self.image_scale)
+            .into_iter()
+            .map(|(shader, params)| {
+                if !self.builtin_pipelines.contains_key(&shader) {
+                    let module = create_shader_module(device, shader.source())
+                        .expect("Invalid built-in shader");
+                    let pipeline = self.build_pipeline(device, &module);
+                    self.builtin_pipelines.insert(shader, pipeline);
+                }
+                self.create_pass(device, queue, PassPipeline::Builtin(shader), &params)
+            })
+            .collect();
+        self.push(
+            device,
+            Filter {
+                builtin: Some(filter),
+                params: params.to_vec(),
+                passes,
+            },
+        )
+    }
+
+    pub fn set_params(&mut self, queue: &wgpu::Queue, index: usize, params: &[f32]) -> bool {
+        let (size, image_scale) = (self.size, self.image_scale);
+        let filter = match self.filters.get_mut(index) {
+            Some(filter) if params.len() <= MAX_FILTER_PARAMS => filter,
+            _ => return false,
+        };
+        filter.params = params.to_vec();
+        Self::update_passes(queue, filter, size, image_scale);
+        true
+    }
+
+    /// Set the screen pixels per image pixel of the shown images, the sizes of
+    /// the built-in filters follow it.
+    pub fn set_image_scale(&mut self, queue: &wgpu::Queue, image_scale: f32) {
+        if image_scale == self.image_scale {
+            return;
+        }
+        self.image_scale = image_scale;
+        for filter in self.filters.iter_mut().filter(|f| f.builtin.is_some()) {
+            Self::update_passes(queue, filter, self.size, image_scale);
+        }
+    }
+
+    fn update_passes(queue: &wgpu::Queue, filter: &mut Filter, size: (u32, u32), image_scale: f32) {
+        let pass_params = filter.pass_params(image_scale);
+        for (pass, params) in filter.passes.iter_mut().zip(pass_params) {
+            pass.params = params;
+            pass.write(queue, size);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index >= self.filters.len() {
+            return false;
+        }
+        self.filters.remove(index);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: (u32, u32)) {
+        self.size = size;
+        for pass in self.filters.iter().flat_map(|filter| filter.passes.iter()) {
+            pass.write(queue, size);
+        }
+        self.create_intermediates(device);
+    }
+
+    /// Where the images are drawn, in `SCENE_FORMAT`.
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.intermediates[0].view
+    }
+
+    /// Where the filters leave their result, the overlays are drawn over it.
+    pub fn result_view(&self) -> &wgpu::TextureView {
+        &self.intermediates[self.pass_count() % 2].view
+    }
+
+    fn pass_count(&self) -> usize {
+        self.filters.iter().map(|filter| filter.passes.len()).sum()
+    }
+
+    /// Record the filter passes, from `scene_view` to `result_view`.
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder) {
+        let passes = self.filters.iter().flat_map(|filter| filter.passes.iter());
+        for (i, filter_pass) in passes.enumerate() {
+            let pipeline = match &filter_pass.pipeline {
+                PassPipeline::Custom(pipeline) => pipeline,
+                PassPipeline::Builtin(shader) => &self.builtin_pipelines[shader],
+            };
+            Self::draw(
+                encoder,
+                &self.intermediates[(i + 1) % 2].view,
+                pipeline,
+                &filter_pass.bind_groups[i % 2],
+            );
+        }
+    }
+
+    /// Record the display transform of `result_view` into `output`.
+    pub fn present(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        Self::draw(
+            encoder,
+            output,
+            &self.output_pipeline,
+            &self.output_bind_groups[self.pass_count() % 2],
+        );
+    }
+
+    fn draw(
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: wgpu::LoadOp::Clear,
+                clear_color: wgpu::Color::BLACK,
+                store_op: wgpu::StoreOp::Store,
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn create_intermediates(&mut self, device: &wgpu::Device) {
+        self.intermediates = (0..2)
+            .map(|_| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    size: wgpu::Extent3d {
+                        width: self.size.0.max(1),
+                        height: self.size.1.max(1),
+                        depth: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: SCENE_FORMAT,
+                    usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+                    label: Some("FilterTexture"),
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: None,
+                    format: SCENE_FORMAT,
+                    dimension: wgpu::TextureViewDimension::D2,
+                    aspect: wgpu::TextureAspect::default(),
+                    base_mip_level: 0,
+                    base_array_layer: 0,
+                    level_count: 1,
+                    array_layer_count: 1,
+                });
+                Intermediate {
+                    _texture: texture,
+                    view,
+                }
+            })
+            .collect();
+        self.output_bind_groups = self.create_bind_groups(device, &self.output_buffer);
+        for index in 0..self.filters.len() {
+            self.bind_filter(device, index);
+        }
+    }
+
+    fn create_pass(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline: PassPipeline,
+        params: &[f32],
+    ) -> Pass {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("FilterUniforms"),
+            size: mem::size_of::<FilterUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let pass = Pass {
+            pipeline,
+            buffer,
+            params: params.to_vec(),
+            bind_groups: Vec::new(),
+        };
+        pass.write(queue, self.size);
+        pass
+    }
+
+    // Append a filter and bind its passes, returns its index.
+    fn push(&mut self, device: &wgpu::Device, filter: Filter) -> usize {
+        self.filters.push(filter);
+        let index = self.filters.len() - 1;
+        self.bind_filter(device, index);
+        index
+    }
+
+    fn bind_filter(&mut self, device: &wgpu::Device, index: usize) {
+        for pass in 0..self.filters[index].passes.len() {
+            let bind_groups =
+                self.create_bind_groups(device, &self.filters[index].passes[pass].buffer);
+            self.filters[index].passes[pass].bind_groups = bind_groups;
+        }
+    }
+
+    // Bind groups reading each of the intermediates, with the uniforms in `buffer`.
+    fn create_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+    ) -> Vec<wgpu::BindGroup> {
+        self.intermediates
+            .iter()
+            .map(|input| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("FilterBindGroup"),
+                    layout: &self.bind_group_layout,
+                    bindings: &[
+                        wgpu::Binding {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&input.view),
+                        },
+                        wgpu::Binding {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::Binding {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                        },
+                    ],
+                })
+            })
+            .collect()
+    }
+
+    fn build_pipeline(
+        &self,
+        device: &wgpu::Device,
+        fs_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        build_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.vertex,
+            fs_module,
+            SCENE_FORMAT,
+        )
+    }
+}
+
+/// Compile the WGSL of a user filter to SPIR-V. A shader that doesn't fit the
+/// filter layout is an error like a compile error, wgpu would panic on it.
+pub fn compile_filter(source: &str) -> Result<Vec<u32>, String> {
+    let module = parse_wgsl(source)?;
+    check_filter_layout(&module)?;
+    Ok(write_spirv(&module))
+}
+
+// A filter has a fragment entry point `main` writing a vec4<f32> at location
+// 0. It can read the texture coordinate at location 0 and the bindings of
+// `FilterChain::bind_group_layout`.
+fn check_filter_layout(module: &naga::Module) -> Result<(), String> {
+    use naga::{
+        Binding, ImageDimension, ImageFlags, ScalarKind, StorageClass, TypeInner, VectorSize,
+    };
+
+    let has_main = module
+        .entry_points
+        .iter()
+        .any(|entry| entry.stage == naga::ShaderStage::Fragment && entry.name == "main");
+    if !has_main {
+        return Err("A filter needs a fragment entry point named main".to_string());
+    }
+    let is_float = |ty: naga::Handle<naga::Type>| match module.types[ty].inner {
+        TypeInner::Scalar { kind, .. } => kind == ScalarKind::Float,
+        _ => false,
+    };
+    let mut has_output = false;
+    for (_, var) in module.global_variables.iter() {
+        let inner = &module.types[var.ty].inner;
+        let error = match (var.class, &var.binding) {
+            (_, None) | (_, Some(Binding::BuiltIn(_))) => None,
+            (StorageClass::Input, Some(Binding::Location(0))) => match inner {
+                TypeInner::Vector {
+                    size: VectorSize::Bi,
+                    kind: ScalarKind::Float,
+                    ..
+                } => None,
+                _ => Some("the texture coordinate is a vec2<f32>"),
+            },
+            (StorageClass::Output, Some(Binding::Location(0))) => {
+                has_output = true;
+                match inner {
+                    TypeInner::Vector {
+                        size: VectorSize::Quad,
+                        kind: ScalarKind::Float,
+                        ..
+                    } => None,
+                    _ => Some("the output is a vec4<f32>"),
+                }
+            }
+            (_, Some(Binding::Location(_))) => Some("the only input and output are at location 0"),
+            (_, Some(Binding::Descriptor { set: 0, binding: 0 })) => match *inner {
+                TypeInner::Image { base, dim, flags }
+                    if dim == ImageDimension::D2
+                        && flags.contains(ImageFlags::SAMPLED)
+                        && !flags.intersects(ImageFlags::ARRAYED | ImageFlags::MULTISAMPLED)
+                        && is_float(base) =>
+                {
+                    None
+                }
+                _ => Some("binding 0 is a sampled 2D float texture"),
+            },
+            (_, Some(Binding::Descriptor { set: 0, binding: 1 })) => match inner {
+                TypeInner::Sampler { comparison: false } => None,
+                _ => Some("binding 1 is a sampler"),
+            },
+            (StorageClass::Uniform, Some(Binding::Descriptor { set: 0, binding: 2 })) => {
+                match inner {
+                    TypeInner::Struct { .. } => match type_size(module, var.ty) {
+                        Some(size) if size <= mem::size_of::<FilterUniforms>() as u64 => None,
+                        _ => Some("binding 2 is no larger than FilterUniforms"),
+                    },
+                    _ => Some("binding 2 is a uniform struct"),
+                }
+            }
+            (_, Some(Binding::Descriptor { .. })) => {
+                Some("only bindings 0 to 2 of set 0 are available")
+            }
+        };
+        if let Some(error) = error {
+            return Err(format!(
+                "{} doesn't fit the filter layout, {}",
+                var.name.as_deref().unwrap_or("A global variable"),
+                error
+            ));
+        }
+    }
+    if !has_output {
+        return Err("A filter writes a vec4<f32> at location 0".to_string());
+    }
+    Ok(())
+}
+
+// Size in bytes of a uniform type, `parse_wgsl` already rejects arrays.
+fn type_size(module: &naga::Module, ty: naga::Handle<naga::Type>) -> Option<u64> {
+    use naga::{MemberOrigin, TypeInner};
+
+    match module.types[ty].inner {
+        TypeInner::Scalar { width, .. } => Some(width as u64),
+        TypeInner::Vector { size, width, .. } => Some(size as u64 * width as u64),
+        // The columns of a matrix are aligned like a vec4.
+        TypeInner::Matrix { columns, width, .. } => Some(columns as u64 * 4 * width as u64),
+        TypeInner::Struct { ref members } => members.iter().try_fold(0, |size: u64, member| {
+            let offset = match member.origin {
+                MemberOrigin::Offset(offset) => offset as u64,
+                MemberOrigin::BuiltIn(_) => 0,
+            };
+            Some(size.max(offset + type_size(module, member.ty)?))
+        }),
+        _ => None,
+    }
+}
+
+/// A pipeline for full-screen passes writing `format`, without blending.
+pub fn build_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        layout,
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            depth_bias: 0,
+            depth_bias_clamp: 0.0,
+            depth_bias_slope_scale: 0.0,
+        }),
+        color_states: &[wgpu::ColorStateDescriptor {
+            format,
+            color_blend: wgpu::BlendDescriptor::REPLACE,
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint16,
+            vertex_buffers: &[],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: &str = "
+type FilterUniforms = struct {
+  [[offset 0]] size : vec2<f32>;
+  [[offset 8]] texel : vec2<f32>;
+};
+";
+
+    fn filter(globals: &str, body: &str) -> String {
+        format!(
+            "{}{}\nfn main() -> void {{\n  {}\n  return;\n}}\nentry_point fragment as \"main\" = main;\n",
+            PARAMS, globals, body
+        )
+    }
+
+    #[test]
+    fn filters_must_fit_the_layout() {
+        let output = "[[location 0]] var<out> f_color : vec4<f32>;";
+        let input = "[[location 0]] var<in> v_tex : vec2<f32>;";
+        let uniforms = "[[binding 2, set 0]] var<uniform> params : FilterUniforms;";
+        let body = "f_color = vec4<f32>(v_tex, 0.0, 1.0);";
+        let globals = [output, input, uniforms].join("\n");
+        assert!(compile_filter(&filter(&globals, body)).is_ok());
+
+        // Doesn't parse.
+        assert!(compile_filter("fn main() -> void {").is_err());
+        // No fragment entry point named main.
+        assert!(
+            compile_filter(&filter(&globals, body).replace("as \"main\"", "as \"other\"")).is_err()
+        );
+        // Uniforms at a binding the layout doesn't have.
+        let globals = [output, input, &uniforms.replace("binding 2", "binding 3")].join("\n");
+        assert!(compile_filter(&filter(&globals, body)).is_err());
+        // Uniforms in another set.
+        let globals = [output, input, &uniforms.replace("set 0", "set 1")].join("\n");
+        assert!(compile_filter(&filter(&globals, body)).is_err());
+        // An output that isn't a vec4.
+        let globals = [
+            "[[location 0]] var<out> f_color : vec2<f32>;",
+            input,
+            uniforms,
+        ]
+        .join("\n");
+        assert!(compile_filter(&filter(&globals, "f_color = v_tex;")).is_err());
+        // An output at another location.
+        let globals = [&output.replace("location 0", "location 1"), input, uniforms].join("\n");
+        assert!(compile_filter(&filter(&globals, body)).is_err());
+        // No output at all.
+        let globals = [input, uniforms].join("\n");
+        assert!(compile_filter(&filter(&globals, "")).is_err());
+        // Uniforms larger than the buffer.
+        let globals = [output, input, uniforms].join("\n");
+        let last = filter(&globals, body).replace("};", "  [[offset 76]] last : f32;\n};");
+        assert!(compile_filter(&last).is_ok());
+        let past = last.replace("offset 76", "offset 80");
+        assert!(compile_filter(&past).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::texture::{Pixels, WindowLevel};
+
+/// A decoded frame of a sequence.
+pub struct Frame {
+    pub pixels: Pixels,
+    /// The window for raw values, None for images shown as is.
+    pub window: Option<WindowLevel>,
+    /// Width and height of the pixels in millimeters, if known.
+    pub pixel_spacing: Option<(f32, f32)>,
+}
+
+/// A sequence of frames decoded on demand, e.g. the pages of a TIFF stack.
+pub trait FrameSource {
+    fn frame_count(&self) -> usize;
+    /// Decode the frame at `index`, errors are returned as text.
+    fn frame(&mut self, index: usize) -> Result<Frame, String>;
+}
+
+/// Stepping and timed playback through the frames of a source.
+pub struct Playback {
+    source: Box<dyn FrameSource>,
+    current: usize,
+    /// Frames per second while playing.
+    rate: Option<f64>,
+    // When the current frame was shown, in milliseconds.
+    shown_at: f64,
+    /// Start over after the last frame, otherwise stop.
+    pub looping: bool,
+}
+
+impl Playback {
+    pub fn new(source: Box<dyn FrameSource>) -> Self {
+        Playback {
+            source,
+            current: 0,
+            rate: None,
+            shown_at: 0.0,
+            looping: true,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.source.frame_count()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.rate.is_some()
+    }
+
+    /// Decode the current frame.
+    pub fn frame(&mut self) -> Result<Frame, String> {
+        self.source.frame(self.current)
+    }
+
+    /// Go to a frame, returns false if out of range.
+    pub fn seek(&mut self, index: usize) -> bool {
+        if index >= self.frame_count() {
+            return false;
+        }
+        self.current = index;
+        true
+    }
+
+    /// Play at `fps` frames per second from the current frame.
+    pub fn play(&mut self, fps: f64, now: f64) {
+        if fps > 0.0 && self.frame_count() > 1 {
+            self.rate = Some(fps);
+            self.shown_at = now;
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.rate = None;
+    }
+
+    /// The frame to show at `now` if it differs from the current one. Frames
+    /// are skipped rather than delayed when rendering can't keep up.
+    pub fn advance(&mut self, now: f64) -> Option<usize> {
+        let rate = self.rate?;
+        let interval = 1000.0 / rate;
+        let steps = ((now - self.shown_at) / interval).floor();
+        if steps < 1.0 {
+            return None;
+        }
+        self.shown_at += steps * interval;
+        let count = self.frame_count();
+        let next = self.current + steps as usize;
+        self.current = if next < count {
+            next
+        } else if self.looping {
+            next % count
+        } else {
+            self.rate = None;
+            count - 1
+        };
+        Some(self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::FloatImage;
+
+    struct Blank(usize);
+
+    impl FrameSource for Blank {
+        fn frame_count(&self) -> usize {
+            self.0
+        }
+
+        fn frame(&mut self, _index: usize) -> Result<Frame, String> {
+            Ok(Frame {
+                pixels: Pixels::Float(FloatImage::new(1, 1)?),
+                window: None,
+                pixel_spacing: None,
+            })
+        }
+    }
+
+    #[test]
+    fn playback_steps_with_time() {
+        let mut playback = Playback::new(Box::new(Blank(3)));
+        playback.play(10.0, 1000.0);
+        assert_eq!(playback.advance(1050.0), None);
+        assert_eq!(playback.advance(1100.0), Some(1));
+        // Two frames late, wraps around.
+        assert_eq!(playback.advance(1320.0), Some(0));
+        assert_eq!(playback.advance(1390.0), None);
+
+        playback.looping = false;
+        assert_eq!(playback.advance(1700.0), Some(2));
+        assert!(!playback.is_playing());
+        assert!(!playback.seek(3));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::texture::FloatImage;
+
+const EXR_MAGIC: &[u8] = &[0x76, 0x2f, 0x31, 0x01];
+
+/// Whether the bytes are an OpenEXR or Radiance HDR file.
+pub fn is_hdr(bytes: &[u8]) -> bool {
+    bytes.starts_with(EXR_MAGIC) || bytes.starts_with(b"#?")
+}
+
+/// Decode an OpenEXR or Radiance HDR file to linear RGBA. Errors are
+/// returned as text.
+pub fn decode(bytes: &[u8]) -> Result<FloatImage, String> {
+    if bytes.starts_with(EXR_MAGIC) {
+        decode_exr(bytes)
+    } else {
+        decode_radiance(bytes)
+    }
+}
+
+fn decode_radiance(bytes: &[u8]) -> Result<FloatImage, String> {
+    let decoder = image::codecs::hdr::HdrDecoder::new(bytes)
+        .map_err(|e| format!("Failed to read Radiance HDR: {}", e))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()
+        .map_err(|e| format!("Failed to read Radiance HDR: {}", e))?;
+    Ok(FloatImage {
+        width: metadata.width,
+        height: metadata.height,
+        pixels: pixels
+            .into_iter()
+            .map(|p| [p.0[0], p.0[1], p.0[2], 1.0])
+            .collect(),
+    })
+}
+
+// The first layer with RGB channels, alpha is 1 if missing. An image too
+// large to allocate is kept as an error until the pixels have been read.
+fn decode_exr(bytes: &[u8]) -> Result<FloatImage, String> {
+    use exr::prelude::*;
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(
+            |resolution, _| FloatImage::new(resolution.width() as u32, resolution.height() as u32),
+            |image: &mut std::result::Result<FloatImage, String>,
+             position,
+             (r, g, b, a): (f32, f32, f32, f32)| {
+                if let Ok(image) = image {
+                    image.set(position.x() as u32, position.y() as u32, [r, g, b, a])
+                }
+            },
+        )
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(std::io::Cursor::new(bytes))
+        .map_err(|e| format!("Failed to read OpenEXR: {}", e))?;
+    image.layer_data.channel_data.pixels
+}
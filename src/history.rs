@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+
+/// A bounded undo/redo stack of snapshots.
+///
+/// Continuous changes (e.g. a drag) are coalesced into a single entry by
+/// wrapping them in `begin_gesture`/`end_gesture`.
+pub struct History<T: Clone> {
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    capacity: usize,
+    in_gesture: bool,
+}
+
+impl<T: Clone> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            capacity,
+            in_gesture: false,
+        }
+    }
+
+    /// Record the state before a discrete change.
+    pub fn record(&mut self, before: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(before);
+        self.redo.clear();
+    }
+
+    /// Record the state before a continuous change, only the first call of
+    /// a gesture is recorded.
+    pub fn begin_gesture(&mut self, before: T) {
+        if !self.in_gesture {
+            self.record(before);
+            self.in_gesture = true;
+        }
+    }
+
+    pub fn end_gesture(&mut self) {
+        self.in_gesture = false;
+    }
+
+    /// Returns the state to go back to, `current` is kept for redo.
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        self.in_gesture = false;
+        Some(previous)
+    }
+
+    /// Returns the state to go forward to, `current` is kept for undo.
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        self.in_gesture = false;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gestures_are_coalesced() {
+        let mut history = History::new(2);
+        history.begin_gesture(0);
+        history.begin_gesture(1);
+        history.end_gesture();
+        history.record(2);
+
+        assert_eq!(history.undo(3), Some(2));
+        assert_eq!(history.undo(2), Some(0));
+        assert_eq!(history.undo(0), None);
+        assert_eq!(history.redo(0), Some(2));
+        assert_eq!(history.redo(2), Some(3));
+        assert_eq!(history.redo(3), None);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let mut history = History::new(2);
+        history.record(0);
+        history.record(1);
+        history.record(2);
+        assert_eq!(history.undo(3), Some(2));
+        assert_eq!(history.undo(2), Some(1));
+        assert_eq!(history.undo(1), None);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+
+// Only pointer samples this recent (ms) are used for the release velocity.
+const SAMPLE_WINDOW: f64 = 100.0;
+// A pointer that rested this long (ms) before release does not fling.
+const MAX_REST_BEFORE_RELEASE: f64 = 50.0;
+// Time constant (ms) of the exponential decay of the velocity.
+const TIME_CONSTANT: f32 = 325.0;
+// Velocities (px/ms) below this stop the motion.
+const MIN_SPEED: f32 = 0.02;
+
+/// Estimates the pointer velocity from timestamped positions.
+#[derive(Debug, Default)]
+pub struct VelocityTracker {
+    samples: VecDeque<(f64, (f32, f32))>,
+}
+
+impl VelocityTracker {
+    pub fn new() -> Self {
+        VelocityTracker::default()
+    }
+
+    pub fn add(&mut self, now: f64, pos: (f32, f32)) {
+        self.samples.push_back((now, pos));
+        while let Some(&(time, _)) = self.samples.front() {
+            if now - time > SAMPLE_WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The velocity in pixels per millisecond at the given time.
+    pub fn velocity(&self, now: f64) -> (f32, f32) {
+        let (first, last) = match (self.samples.front(), self.samples.back()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return (0.0, 0.0),
+        };
+        let dt = (last.0 - first.0) as f32;
+        if dt <= 0.0 || now - last.0 > MAX_REST_BEFORE_RELEASE {
+            return (0.0, 0.0);
+        }
+        ((last.1 .0 - first.1 .0) / dt, (last.1 .1 - first.1 .1) / dt)
+    }
+}
+
+/// Motion that continues after a drag with exponentially decaying velocity.
+#[derive(Debug)]
+pub struct Inertia {
+    velocity: (f32, f32),
+    last: f64,
+}
+
+impl Inertia {
+    /// Start a motion at the given time, returns None if the velocity is too low.
+    pub fn start(velocity: (f32, f32), now: f64) -> Option<Self> {
+        if speed(velocity) < MIN_SPEED {
+            return None;
+        }
+        Some(Inertia {
+            velocity,
+            last: now,
+        })
+    }
+
+    /// The displacement since the last step.
+    pub fn step(&mut self, now: f64) -> (f32, f32) {
+        let dt = (now - self.last).max(0.0) as f32;
+        self.last = now;
+        // Integral of v * exp(-t / T) over the time step
+        let decay = (-dt / TIME_CONSTANT).exp();
+        let distance = TIME_CONSTANT * (1.0 - decay);
+        let delta = (self.velocity.0 * distance, self.velocity.1 * distance);
+        self.velocity = (self.velocity.0 * decay, self.velocity.1 * decay);
+        delta
+    }
+
+    pub fn is_finished(&self) -> bool {
+        speed(self.velocity) < MIN_SPEED
+    }
+}
+
+fn speed(velocity: (f32, f32)) -> f32 {
+    (velocity.0 * velocity.0 + velocity.1 * velocity.1).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn velocity_from_recent_samples() {
+        let mut tracker = VelocityTracker::new();
+        tracker.add(0.0, (0.0, 0.0));
+        tracker.add(100.0, (0.0, 0.0));
+        tracker.add(150.0, (50.0, 0.0));
+        tracker.add(200.0, (100.0, -50.0));
+        assert_eq!(tracker.velocity(200.0), (1.0, -0.5));
+        // The pointer rested before it was released.
+        assert_eq!(tracker.velocity(300.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn inertia_decays() {
+        assert!(Inertia::start((0.01, 0.0), 0.0).is_none());
+
+        let mut inertia = Inertia::start((1.0, 0.0), 0.0).unwrap();
+        let mut total = 0.0;
+        let mut now = 0.0;
+        while !inertia.is_finished() {
+            now += 16.0;
+            total += inertia.step(now).0;
+        }
+        // The full glide is v * T, minus what remains below the stop speed.
+        assert!(total > 0.95 * TIME_CONSTANT && total < TIME_CONSTANT);
+    }
+}
@@ -0,0 +1,125 @@
+/// A rectangle on the canvas, in pixels with the origin in the upper left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn size(&self) -> (f32, f32) {
+        (self.width as f32, self.height as f32)
+    }
+
+    pub fn contains(&self, pos: (f32, f32)) -> bool {
+        pos.0 >= self.x as f32
+            && pos.1 >= self.y as f32
+            && pos.0 < (self.x + self.width) as f32
+            && pos.1 < (self.y + self.height) as f32
+    }
+
+    /// Convert a canvas position to a position relative to this rectangle.
+    pub fn to_local(&self, pos: (f32, f32)) -> (f32, f32) {
+        (pos.0 - self.x as f32, pos.1 - self.y as f32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Single,
+    SideBySide,
+    /// Rows x columns of panes, ordered row by row.
+    Grid {
+        rows: u32,
+        cols: u32,
+    },
+}
+
+impl Layout {
+    pub fn pane_count(&self) -> usize {
+        match *self {
+            Layout::Single => 1,
+            Layout::SideBySide => 2,
+            Layout::Grid { rows, cols } => (rows.max(1) * cols.max(1)) as usize,
+        }
+    }
+
+    /// Split a canvas of the given size into one viewport per pane.
+    pub fn viewports(&self, size: (u32, u32)) -> Vec<Rect> {
+        match *self {
+            Layout::Single => vec![Rect::new(0, 0, size.0, size.1)],
+            Layout::SideBySide => {
+                let left = size.0 / 2;
+                vec![
+                    Rect::new(0, 0, left, size.1),
+                    Rect::new(left, 0, size.0 - left, size.1),
+                ]
+            }
+            Layout::Grid { rows, cols } => {
+                let (rows, cols) = (rows.max(1), cols.max(1));
+                // Distribute any remainder so the cells cover the whole canvas.
+                let split = |extent: u32, count: u32, i: u32| {
+                    let start = extent * i / count;
+                    (start, extent * (i + 1) / count - start)
+                };
+                let mut rects = Vec::with_capacity((rows * cols) as usize);
+                for row in 0..rows {
+                    let (y, height) = split(size.1, rows, row);
+                    for col in 0..cols {
+                        let (x, width) = split(size.0, cols, col);
+                        rects.push(Rect::new(x, y, width, height));
+                    }
+                }
+                rects
+            }
+        }
+    }
+}
+
+/// How view changes in one pane are propagated to the other panes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewLink {
+    /// Each pane is panned and zoomed on its own.
+    Independent,
+    /// Pan and zoom deltas are applied to all panes, keeping any offsets between them.
+    Linked,
+    /// All panes show the same view.
+    Shared,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_by_side_covers_canvas() {
+        let rects = Layout::SideBySide.viewports((641, 480));
+        assert_eq!(rects[0], Rect::new(0, 0, 320, 480));
+        assert_eq!(rects[1], Rect::new(320, 0, 321, 480));
+        assert!(rects[1].contains((320.0, 0.0)));
+        assert!(!rects[0].contains((320.0, 0.0)));
+        assert_eq!(rects[1].to_local((330.0, 10.0)), (10.0, 10.0));
+    }
+
+    #[test]
+    fn grid_covers_canvas() {
+        let layout = Layout::Grid { rows: 2, cols: 3 };
+        let rects = layout.viewports((100, 51));
+        assert_eq!(rects.len(), layout.pane_count());
+        assert_eq!(rects[0], Rect::new(0, 0, 33, 25));
+        assert_eq!(rects[2], Rect::new(66, 0, 34, 25));
+        assert_eq!(rects[5], Rect::new(66, 25, 34, 26));
+        let area: u32 = rects.iter().map(|r| r.width * r.height).sum();
+        assert_eq!(area, 100 * 51);
+    }
+}
@@ -20,10 +20,38 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod animation;
+mod color;
+mod compare;
+mod convolution;
+mod dicom;
+mod display;
+mod filters;
+mod frames;
+mod hdr;
+mod history;
+mod kinetic;
+mod layout;
+mod loupe;
+mod minimap;
+mod pane;
+mod raw;
 mod render_target;
+mod shaders;
+mod sync;
+mod texture;
+mod tiff_frames;
 mod vertex;
 mod view_state;
+use color::ToneMap;
+use compare::CompareMode;
+use convolution::BuiltinFilter;
+use display::{AlphaMode, Channel, Interpolation};
+use layout::{Layout, ViewLink};
+use raw::{Endianness, PixelFormat};
 use render_target::{SwapchainTarget, TextureTarget};
+use sync::{SyncGroup, SyncMember, SyncSpace};
+use view_state::Zoom;
 mod renderer;
 use raw_window_handle::HasRawWindowHandle;
 use renderer::State;
@@ -39,10 +67,20 @@ async fn create_for_window(window: &Window) -> State<SwapchainTarget> {
 
     let target = SwapchainTarget::new(surface, format);
 
-    State::new(instance, (size.width, size.height), target).await
+    State::new(
+        instance,
+        (size.width, size.height),
+        window.scale_factor(),
+        target,
+    )
+    .await
 }
 
-async fn create_for_handle(window: &CanvasWindow, size: (u32, u32)) -> State<SwapchainTarget> {
+async fn create_for_handle(
+    window: &CanvasWindow,
+    size: (u32, u32),
+    scale_factor: f64,
+) -> State<SwapchainTarget> {
     //let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
     let instance = wgpu::Instance::new();
     let surface = unsafe { instance.create_surface(window) };
@@ -51,7 +89,7 @@ async fn create_for_handle(window: &CanvasWindow, size: (u32, u32)) -> State<Swa
 
     let target = SwapchainTarget::new(surface, format);
 
-    State::new(instance, (size.0, size.1), target).await
+    State::new(instance, (size.0, size.1), scale_factor, target).await
 }
 
 fn create_window() -> (
@@ -83,8 +121,7 @@ fn run_event_loop(event_loop: winit::event_loop::EventLoop<StateSetup>) {
     let mut mouse_down = false;
     let mut ctrl_down = false;
 
-    let (mut mx, mut my) = (100.0_f32, 100.0_f32);
-    let mut delta = 3.5_f32;
+    let mut cursor = (0.0_f32, 0.0_f32);
 
     //let mut last_now = Instant::now();
     let perf = Some(web_sys::window().unwrap().performance().unwrap());
@@ -136,6 +173,144 @@ fn run_event_loop(event_loop: winit::event_loop::EventLoop<StateSetup>) {
                         } => {
                             state.swap_image();
                         }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Z),
+                            ..
+                        } if ctrl_down => {
+                            state.undo();
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Y),
+                            ..
+                        } if ctrl_down => {
+                            state.redo();
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::R),
+                            ..
+                        } => {
+                            state.reset_view(true);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key @ VirtualKeyCode::Key1),
+                            ..
+                        }
+                        | KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key @ VirtualKeyCode::F),
+                            ..
+                        }
+                        | KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key @ VirtualKeyCode::W),
+                            ..
+                        }
+                        | KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key @ VirtualKeyCode::H),
+                            ..
+                        }
+                        | KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key @ VirtualKeyCode::X),
+                            ..
+                        } => {
+                            let zoom = match key {
+                                VirtualKeyCode::Key1 => Zoom::Pixel(1.0),
+                                VirtualKeyCode::W => Zoom::FitWidth(1.0),
+                                VirtualKeyCode::H => Zoom::FitHeight(1.0),
+                                VirtualKeyCode::X => Zoom::Fill(1.0),
+                                _ => Zoom::Fit(1.0),
+                            };
+                            state.set_zoom_mode(zoom, true);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::S),
+                            ..
+                        } => {
+                            // Toggle the side-by-side comparison
+                            let layout = match state.layout() {
+                                Layout::Single => Layout::SideBySide,
+                                _ => Layout::Single,
+                            };
+                            state.set_layout(layout);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::G),
+                            ..
+                        } => {
+                            // Toggle a 2x2 grid
+                            let layout = match state.layout() {
+                                Layout::Grid { .. } => Layout::Single,
+                                _ => Layout::Grid { rows: 2, cols: 2 },
+                            };
+                            state.set_layout(layout);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::L),
+                            ..
+                        } => {
+                            let view_link = match state.view_link() {
+                                ViewLink::Independent => ViewLink::Linked,
+                                ViewLink::Linked => ViewLink::Shared,
+                                ViewLink::Shared => ViewLink::Independent,
+                            };
+                            state.set_view_link(view_link);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::C),
+                            ..
+                        } => {
+                            // Cycle through the compare modes
+                            let mode = match state.compare_mode() {
+                                None => Some(CompareMode::Swipe),
+                                Some(mode) => mode.next(),
+                            };
+                            state.set_compare_mode(mode);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::P),
+                            ..
+                        } => {
+                            let display = state.display();
+                            let (show_grid, show_values) =
+                                (!display.show_grid, display.show_values);
+                            state.set_pixel_grid(show_grid, show_values);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::V),
+                            ..
+                        } => {
+                            let display = state.display();
+                            let (show_grid, show_values) =
+                                (display.show_grid, !display.show_values);
+                            state.set_pixel_grid(show_grid, show_values);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::M),
+                            ..
+                        } => {
+                            state.set_minimap(!state.minimap_enabled(), 160.0);
+                        }
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::O),
+                            ..
+                        } => {
+                            state.set_loupe(!state.loupe_enabled(), 80.0, 4.0);
+                            state.set_loupe_position(Some(cursor));
+                        }
                         _ => {}
                     },
                     WindowEvent::Resized(physical_size) => {
@@ -143,7 +318,11 @@ fn run_event_loop(event_loop: winit::event_loop::EventLoop<StateSetup>) {
                             .state
                             .resize((physical_size.width, physical_size.height));
                     }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    } => {
+                        setup.state.set_scale_factor(*scale_factor);
                         // new_inner_size is &mut so w have to dereference it twice
                         setup
                             .state
@@ -160,11 +339,16 @@ fn run_event_loop(event_loop: winit::event_loop::EventLoop<StateSetup>) {
                             _ => ctrl_down = false,
                         };
 
+                        // The state takes positions in logical pixels.
+                        let logical = position.to_logical::<f32>(window.scale_factor());
+                        cursor = (logical.x, logical.y);
+                        state.set_loupe_position(Some(cursor));
                         if mouse_down {
                             if ctrl_down {
-                                state.update_zoom((position.x as f32, position.y as f32));
+                                state.update_zoom(cursor);
                             } else {
-                                state.update_position((position.x as f32, position.y as f32));
+                                let n = if let Some(p) = &perf { p.now() } else { 0.0 };
+                                state.update_position(cursor, n);
                                 //info!("{:?}", position);
                             }
                             //state.render();
@@ -175,16 +359,23 @@ fn run_event_loop(event_loop: winit::event_loop::EventLoop<StateSetup>) {
                             //info!("Frame time: {}", diff);
                         }
                     }
+                    WindowEvent::CursorLeft { .. } => {
+                        state.set_loupe_position(None);
+                    }
                     WindowEvent::MouseInput {
                         state: elem_state,
                         button,
                         ..
                     } => match button {
                         MouseButton::Left => match elem_state {
-                            ElementState::Pressed => mouse_down = true,
+                            ElementState::Pressed => {
+                                mouse_down = true;
+                                state.activate_pane_at(cursor);
+                            }
                             _ => {
                                 mouse_down = false;
-                                state.clear_anchor();
+                                let n = if let Some(p) = &perf { p.now() } else { 0.0 };
+                                state.clear_anchor(n);
                             }
                         },
                         _ => {}
@@ -208,20 +399,15 @@ fn run_event_loop(event_loop: winit::event_loop::EventLoop<StateSetup>) {
                 // }
             }
             Event::MainEventsCleared => {
+                // Advance any view transitions, this marks the state as dirty while running.
+                if let Some(p) = &perf {
+                    state.animate(p.now());
+                }
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
-                // if state.is_dirty() {
-                //     window.request_redraw();
-                // }
-                if mx > 300_f32 {
-                    delta *= -1.0_f32;
-                } else if mx < -100.0_f32 {
-                    delta *= -1.0_f32;
+                if state.is_dirty() {
+                    window.request_redraw();
                 }
-                mx += delta;
-                state.update_position((mx, my));
-                window.request_redraw();
-                //state.render();
             }
             _ => {}
         }
@@ -263,6 +449,30 @@ pub fn entry() {
 #[wasm_bindgen]
 struct RenderController {
     state: State<SwapchainTarget>,
+    sync: Option<SyncMember>,
+}
+
+/// A group of controllers whose views follow each other.
+#[wasm_bindgen]
+pub struct ViewSyncGroup {
+    group: SyncGroup,
+}
+
+#[wasm_bindgen]
+impl ViewSyncGroup {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ViewSyncGroup {
+        ViewSyncGroup {
+            group: SyncGroup::new(),
+        }
+    }
+}
+
+/// Milliseconds from the performance clock.
+fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0.0, |perf| perf.now())
 }
 
 struct CanvasWindow {
@@ -285,10 +495,16 @@ impl RenderController {
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
         console_log::init().expect("could not initialize logger");
 
+        // The canvas size is in CSS pixels, render in device pixels to keep HiDPI displays sharp.
+        let scale_factor = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio());
+        let size = (
+            (width as f64 * scale_factor).round() as u32,
+            (height as f64 * scale_factor).round() as u32,
+        );
         let window = CanvasWindow { id: canvas_id };
-        let mut state = create_for_handle(&window, (width, height)).await;
+        let mut state = create_for_handle(&window, size, scale_factor).await;
 
-        RenderController { state }
+        RenderController { state, sync: None }
 
         // let canvas = web_sys::window()
         //     .unwrap()
@@ -311,6 +527,8 @@ impl RenderController {
     }
 
     pub fn render(&mut self) {
+        self.apply_sync();
+        self.publish_changes(|state| state.animate(now()));
         self.state.render();
         log::info!("Render called");
     }
@@ -320,18 +538,454 @@ impl RenderController {
     }
 
     pub fn update_position(&mut self, x: f32, y: f32) {
-        self.state.update_position((x, y));
+        let change = self.state.update_position((x, y), now());
+        self.publish(change);
     }
 
     pub fn update_zoom(&mut self, x: f32, y: f32) {
-        self.state.update_zoom((x, y));
+        let change = self.state.update_zoom((x, y));
+        self.publish(change);
+    }
+
+    /// Follow the other controllers in the group. `properties` is a combination
+    /// of 1 (pan), 2 (zoom), 4 (window) and 8 (frame). With `image_relative`
+    /// pans are replayed in image pixels instead of screen pixels.
+    pub fn join_sync_group(
+        &mut self,
+        group: &ViewSyncGroup,
+        properties: u32,
+        image_relative: bool,
+    ) {
+        let space = if image_relative {
+            SyncSpace::Image
+        } else {
+            SyncSpace::Screen
+        };
+        self.sync = Some(group.group.join(properties, space));
+    }
+
+    pub fn leave_sync_group(&mut self) {
+        self.sync = None;
+    }
+
+    /// Apply the changes made by the other controllers in the sync group,
+    /// returns true if the view changed and needs to be rendered.
+    pub fn apply_sync(&mut self) -> bool {
+        let member = match &self.sync {
+            Some(member) => member,
+            None => return false,
+        };
+        let changes = member.poll();
+        for change in changes.iter() {
+            self.state.apply_view_change(*change, member.space);
+        }
+        !changes.is_empty()
+    }
+
+    fn publish(&self, change: Option<sync::ViewChange>) {
+        if let (Some(member), Some(change)) = (&self.sync, change) {
+            member.publish(change);
+        }
+    }
+
+    // Run `change` and publish how it changed the active pane.
+    fn publish_changes<T>(&mut self, change: impl FnOnce(&mut State<SwapchainTarget>) -> T) -> T {
+        let member = match &self.sync {
+            Some(member) => member,
+            None => return change(&mut self.state),
+        };
+        let before = self.state.sync_snapshot();
+        let result = change(&mut self.state);
+        for change in before.changes_to(&self.state.sync_snapshot()) {
+            member.publish(change);
+        }
+        result
     }
 
     pub fn clear_anchor(&mut self) {
-        self.state.clear_anchor();
+        self.state.clear_anchor(now());
     }
 
+    pub fn reset_view(&mut self, animated: bool) {
+        self.publish_changes(|state| state.reset_view(animated));
+    }
+
+    /// Zoom so that the rectangle in image pixels fills the viewport.
+    pub fn zoom_to_rect(&mut self, x: f32, y: f32, width: f32, height: f32, animated: bool) {
+        self.publish_changes(|state| state.zoom_to_rect((x, y, width, height), animated));
+    }
+
+    /// Center the view on a point in image pixels.
+    pub fn pan_to_point(&mut self, x: f32, y: f32, animated: bool) {
+        self.publish_changes(|state| state.pan_to_point((x, y), animated));
+    }
+
+    /// Switch to one of the zoom modes "fit", "fit_width", "fit_height", "fill"
+    /// or "pixel", e.g. ("pixel", 1.0) shows one image pixel per screen pixel.
+    pub fn set_zoom_mode(
+        &mut self,
+        mode: &str,
+        magnification: f32,
+        animated: bool,
+    ) -> Result<(), JsValue> {
+        let zoom = Zoom::from_name(mode, magnification)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown zoom mode: {}", mode)))?;
+        self.publish_changes(|state| state.set_zoom_mode(zoom, animated));
+        Ok(())
+    }
+
+    /// True while a view transition is running, keep calling `render` until it is done.
+    pub fn is_animating(&self) -> bool {
+        self.state.is_animating()
+    }
+
+    pub fn undo(&mut self) {
+        self.publish_changes(|state| state.undo());
+    }
+
+    /// Limit zoom and pan. `min_magnification` is relative to fitting the image,
+    /// `max_magnification` is in screen pixels per image pixel and `min_visible`
+    /// is the fraction of the image that must stay on screen.
+    pub fn set_view_constraints(
+        &mut self,
+        min_magnification: f32,
+        max_magnification: f32,
+        min_visible: f32,
+        elastic: bool,
+    ) {
+        self.state.set_constraints(view_state::ViewConstraints {
+            min_magnification,
+            max_magnification,
+            min_visible,
+            elastic,
+        });
+    }
+
+    /// The views of all panes as JSON, e.g. `[{"zoom":{"fit":2.0},"center":[0.5,0.5]}]`.
+    /// Centers are relative to the image size, so the view can be restored
+    /// on a canvas of any size.
+    pub fn get_view(&self) -> String {
+        serde_json::to_string(&self.state.saved_views()).unwrap()
+    }
+
+    /// Restore views returned from `get_view`, pane by pane.
+    pub fn set_view(&mut self, json: &str) -> Result<(), JsValue> {
+        let views: Vec<view_state::SavedView> =
+            serde_json::from_str(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.publish_changes(|state| state.restore_saved_views(&views));
+        Ok(())
+    }
+
+    pub fn redo(&mut self) {
+        self.publish_changes(|state| state.redo());
+    }
+
+    /// Resize to a size in CSS pixels.
     pub fn set_viewport_size(&mut self, width: u32, height: u32) {
-        self.state.resize((width, height));
+        self.state.resize_logical((width as f64, height as f64));
+    }
+
+    /// Update the device pixel ratio, e.g. when the page is zoomed or moved to another display.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.state.set_scale_factor(scale_factor);
+    }
+
+    /// Load an encoded image (e.g. PNG, OpenEXR or Radiance HDR) into the given image slot.
+    pub fn load_image(&mut self, slot: usize, data: &[u8]) -> Result<(), JsValue> {
+        self.state
+            .load_image(slot, data)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// The number of frames in an image slot, e.g. the pages of a TIFF stack.
+    pub fn frame_count(&self, slot: usize) -> usize {
+        self.state.frame_count(slot)
+    }
+
+    pub fn current_frame(&self, slot: usize) -> usize {
+        self.state.current_frame(slot)
+    }
+
+    pub fn set_frame(&mut self, slot: usize, index: usize) -> Result<(), JsValue> {
+        self.publish_changes(|state| state.set_frame(slot, index))
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Play the frames of an image slot at `fps` frames per second.
+    pub fn play(&mut self, slot: usize, fps: f64, looping: bool) {
+        self.state.play(slot, fps, looping, now());
+    }
+
+    pub fn pause(&mut self, slot: usize) {
+        self.state.pause(slot);
+    }
+
+    pub fn is_playing(&self, slot: usize) -> bool {
+        self.state.is_playing(slot)
+    }
+
+    /// Load a headerless buffer, `format` is one of "gray8", "gray16", "rgb8",
+    /// "rgba8", "bgra8" or "bayer_<pattern><bits>" e.g. "bayer_rggb8" or
+    /// "bayer_gbrg16". A stride of 0 means packed rows, `endianness` is
+    /// "little" or "big".
+    #[allow(clippy::too_many_arguments)]
+    pub fn load_raw(
+        &mut self,
+        slot: usize,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: &str,
+        stride: u32,
+        endianness: &str,
+    ) -> Result<(), JsValue> {
+        let format = format
+            .parse::<PixelFormat>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        let endianness = endianness
+            .parse::<Endianness>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.state
+            .load_raw(slot, data, width, height, format, stride, endianness)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    pub fn set_pane_image(&mut self, pane: usize, slot: usize) {
+        self.state.set_pane_image(pane, slot);
+    }
+
+    /// Show a grid of rows x cols panes, a 1x1 grid is the normal single view.
+    pub fn set_grid(&mut self, rows: u32, cols: u32) {
+        let layout = match (rows, cols) {
+            (0..=1, 0..=1) => Layout::Single,
+            _ => Layout::Grid { rows, cols },
+        };
+        self.state.set_layout(layout);
+    }
+
+    /// Activate the pane at the given canvas position, returns the index of
+    /// the pane or -1 if there is no pane at the position.
+    pub fn activate_pane_at(&mut self, x: f32, y: f32) -> i32 {
+        self.state
+            .activate_pane_at((x, y))
+            .map_or(-1, |index| index as i32)
+    }
+
+    pub fn active_pane(&self) -> usize {
+        self.state.active_pane()
+    }
+
+    pub fn set_side_by_side(&mut self, enabled: bool) {
+        let layout = if enabled {
+            Layout::SideBySide
+        } else {
+            Layout::Single
+        };
+        self.state.set_layout(layout);
+    }
+
+    /// Link the panes of a side-by-side view, `shared` makes all panes show the same view.
+    pub fn set_view_link(&mut self, linked: bool, shared: bool) {
+        let view_link = match (linked, shared) {
+            (_, true) => ViewLink::Shared,
+            (true, false) => ViewLink::Linked,
+            (false, false) => ViewLink::Independent,
+        };
+        self.state.set_view_link(view_link);
+    }
+
+    /// Compare against another image slot using one of "swipe", "difference",
+    /// "checkerboard" or "onion_skin". An empty mode turns the comparison off.
+    /// The other image is aligned pixel for pixel with the top left corner of
+    /// the image in the pane, not scaled to its size.
+    pub fn set_compare_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        let mode = match mode {
+            "" => None,
+            _ => Some(
+                mode.parse::<CompareMode>()
+                    .map_err(|e| JsValue::from_str(&e))?,
+            ),
+        };
+        self.state.set_compare_mode(mode);
+        Ok(())
+    }
+
+    pub fn set_compare_image(&mut self, slot: usize) {
+        self.state.set_compare_image(slot);
+    }
+
+    pub fn set_compare_params(&mut self, gain: f32, opacity: f32, checker_size: f32) {
+        self.state.set_compare_params(gain, opacity, checker_size);
+    }
+
+    /// Show lines between the image pixels and their values when zoomed in.
+    /// The values are shown for 8-bit images, `probe` gives the values of
+    /// float and raw images.
+    pub fn set_pixel_grid(&mut self, show_grid: bool, show_values: bool) {
+        self.state.set_pixel_grid(show_grid, show_values);
+    }
+
+    /// Append a post-processing filter to the chain applied to the shown images,
+    /// returns its index. The filter is a WGSL fragment shader with entry point
+    /// `main` that gets the texture coordinate at location 0 and, in group 0,
+    /// the input texture (binding 0), a sampler (binding 1) and a uniform block
+    /// (binding 2) with the input size in pixels (`vec2<f32>`), the size of a
+    /// pixel in texture coordinates (`vec2<f32>`) and up to 16 parameters
+    /// (four `vec4<f32>`, arrays aren't supported). The input and output are
+    /// premultiplied linear light, transparent where no image is shown. The
+    /// pixel grid, minimaps and loupe are drawn over the output. Compile errors
+    /// and shaders that don't fit this layout are thrown as exceptions.
+    pub fn add_filter(&mut self, wgsl_source: &str, params: &[f32]) -> Result<usize, JsValue> {
+        self.state
+            .add_filter(wgsl_source, params)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Append a built-in filter, returns its index. `name` is one of
+    /// "gaussian_blur" (sigma), "unsharp_mask" (sigma, amount), "sobel" (scale),
+    /// "median" (radius 1 or 2), "open" or "close" (radius). Sizes are in
+    /// image pixels at the zoom of the active pane.
+    pub fn add_builtin_filter(&mut self, name: &str, params: &[f32]) -> Result<usize, JsValue> {
+        let filter = name
+            .parse::<BuiltinFilter>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(self.state.add_builtin_filter(filter, params))
+    }
+
+    pub fn set_filter_params(&mut self, index: usize, params: &[f32]) -> Result<(), JsValue> {
+        if self.state.set_filter_params(index, params) {
+            Ok(())
+        } else {
+            Err(JsValue::from_str(
+                "Invalid filter index or too many parameters",
+            ))
+        }
+    }
+
+    pub fn remove_filter(&mut self, index: usize) -> bool {
+        self.state.remove_filter(index)
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.state.clear_filters();
+    }
+
+    /// Show a circular loupe of `radius` CSS pixels around the cursor, magnifying
+    /// the view under it by `magnification`.
+    pub fn set_loupe(&mut self, enabled: bool, radius: f32, magnification: f32) {
+        self.state.set_loupe(enabled, radius, magnification);
+    }
+
+    /// Move the loupe to the cursor position in CSS pixels.
+    pub fn set_loupe_position(&mut self, x: f32, y: f32) {
+        self.state.set_loupe_position(Some((x, y)));
+    }
+
+    pub fn hide_loupe(&mut self) {
+        self.state.set_loupe_position(None);
+    }
+
+    /// Show a navigator thumbnail in each pane, `size` is its longer side in CSS pixels.
+    /// Dragging inside the thumbnail pans the view.
+    pub fn set_minimap(&mut self, enabled: bool, size: f32) {
+        self.state.set_minimap(enabled, size);
+    }
+
+    /// Resample the image with one of "nearest", "linear", "bicubic",
+    /// "catmull_rom" or "lanczos3".
+    pub fn set_interpolation(&mut self, interpolation: &str) -> Result<(), JsValue> {
+        let interpolation = interpolation
+            .parse::<Interpolation>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.state.set_interpolation(interpolation);
+        Ok(())
+    }
+
+    /// Brighten or darken the displayed image by a number of stops.
+    pub fn set_exposure(&mut self, stops: f32) {
+        self.state.set_exposure(stops);
+    }
+
+    /// Bring values above 1 into the display range with "clamp", "reinhard" or "aces".
+    pub fn set_tone_map(&mut self, tone_map: &str) -> Result<(), JsValue> {
+        let tone_map = tone_map
+            .parse::<ToneMap>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.state.set_tone_map(tone_map);
+        Ok(())
+    }
+
+    /// The image pixel under a canvas position and its value as
+    /// [x, y, r, g, b, a], undefined if there is no image there. 8-bit values
+    /// are in 0..1 as stored, float images give the values as loaded.
+    pub fn probe(&self, x: f32, y: f32) -> Option<Vec<f32>> {
+        self.state.probe((x, y)).map(|((px, py), value)| {
+            vec![px as f32, py as f32, value[0], value[1], value[2], value[3]]
+        })
+    }
+
+    /// Show one of "red", "green", "blue", "alpha" or "luminance" as gray, or "all".
+    pub fn set_channel(&mut self, channel: &str) -> Result<(), JsValue> {
+        let channel = channel
+            .parse::<Channel>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.state.set_channel(channel);
+        Ok(())
+    }
+
+    /// Show transparent pixels "opaque", over a "checkerboard" with squares of
+    /// `checker_size` pixels or over the "background" color.
+    pub fn set_alpha_mode(&mut self, mode: &str, checker_size: f32) -> Result<(), JsValue> {
+        let mode = mode
+            .parse::<AlphaMode>()
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.state.set_alpha_mode(mode, checker_size);
+        Ok(())
+    }
+
+    /// The color around the images, as sRGB in 0..1.
+    pub fn set_background(&mut self, r: f32, g: f32, b: f32) {
+        self.state.set_background([r, g, b]);
+    }
+
+    /// Window the raw values of the image in the active pane, e.g. 16-bit TIFF
+    /// pages or DICOM. A negative width inverts.
+    pub fn set_window(&mut self, center: f32, width: f32) {
+        self.publish_changes(|state| state.set_window(center, width));
+    }
+
+    pub fn reset_window(&mut self) {
+        self.publish_changes(|state| state.reset_window());
+    }
+
+    /// The window of the image in the active pane as [center, width].
+    pub fn window(&self) -> Option<Vec<f32>> {
+        self.state
+            .window()
+            .map(|window| vec![window.center, window.width])
+    }
+
+    /// The distance between two canvas positions as [pixels] or, for images
+    /// with a known pixel spacing, [pixels, millimeters].
+    pub fn measure(&self, x0: f32, y0: f32, x1: f32, y1: f32) -> Option<Vec<f32>> {
+        self.state
+            .measure((x0, y0), (x1, y1))
+            .map(|(pixels, millimeters)| {
+                let mut result = vec![pixels];
+                result.extend(millimeters);
+                result
+            })
+    }
+
+    /// Width and height of the pixels of the image in the active pane in millimeters.
+    pub fn pixel_spacing(&self) -> Option<Vec<f32>> {
+        self.state
+            .pixel_spacing()
+            .map(|(width, height)| vec![width, height])
+    }
+
+    /// Switch to nearest neighbor sampling from this many device pixels per image pixel.
+    pub fn set_nearest_threshold(&mut self, scale: f32) {
+        self.state.set_nearest_threshold(scale);
     }
 }
@@ -0,0 +1,88 @@
+use crate::layout::Rect;
+
+/// A circular inset around the cursor that shows the image at a higher
+/// magnification than the view under it.
+#[derive(Debug)]
+pub struct Loupe {
+    pub enabled: bool,
+    /// Radius in logical pixels.
+    pub radius: f32,
+    /// Magnification relative to the view under the cursor.
+    pub magnification: f32,
+    /// Center in physical canvas pixels, None when the cursor is not over the canvas.
+    pub position: Option<(f32, f32)>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LoupeUniforms {
+    /// In physical canvas pixels.
+    center: [f32; 2],
+    radius: f32,
+    border: f32,
+}
+unsafe impl bytemuck::Pod for LoupeUniforms {}
+unsafe impl bytemuck::Zeroable for LoupeUniforms {}
+
+impl Loupe {
+    pub fn new() -> Self {
+        Loupe {
+            enabled: false,
+            radius: 80.0,
+            magnification: 4.0,
+            position: None,
+        }
+    }
+
+    /// The square around the loupe clipped to `bounds`, in physical pixels.
+    /// None if the loupe is not shown in the bounds.
+    pub fn rect(&self, bounds: Rect, scale_factor: f32) -> Option<Rect> {
+        let center = self.position.filter(|_| self.enabled)?;
+        let radius = self.radius * scale_factor;
+        let left = (center.0 - radius).max(bounds.x as f32).floor() as u32;
+        let top = (center.1 - radius).max(bounds.y as f32).floor() as u32;
+        let right = (center.0 + radius)
+            .min((bounds.x + bounds.width) as f32)
+            .ceil() as u32;
+        let bottom = (center.1 + radius)
+            .min((bounds.y + bounds.height) as f32)
+            .ceil() as u32;
+        if right <= left || bottom <= top {
+            return None;
+        }
+        Some(Rect::new(left, top, right - left, bottom - top))
+    }
+
+    pub fn uniforms(&self, scale_factor: f32) -> LoupeUniforms {
+        let center = self.position.unwrap_or((0.0, 0.0));
+        LoupeUniforms {
+            center: [center.0, center.1],
+            radius: self.radius * scale_factor,
+            border: 2.0 * scale_factor,
+        }
+    }
+}
+
+impl Default for Loupe {
+    fn default() -> Self {
+        Loupe::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_is_clipped_to_bounds() {
+        let mut loupe = Loupe::new();
+        loupe.position = Some((100.0, 50.0));
+        let bounds = Rect::new(0, 0, 400, 300);
+        assert_eq!(loupe.rect(bounds, 1.0), None);
+
+        loupe.enabled = true;
+        assert_eq!(loupe.rect(bounds, 0.5), Some(Rect::new(60, 10, 80, 80)));
+        assert_eq!(loupe.rect(bounds, 1.0), Some(Rect::new(20, 0, 160, 130)));
+        assert_eq!(loupe.rect(Rect::new(200, 0, 200, 300), 1.0), None);
+    }
+}
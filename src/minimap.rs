@@ -0,0 +1,123 @@
+use crate::layout::Rect;
+
+/// A thumbnail of the whole image in the corner of each pane, with the
+/// region visible in the pane outlined.
+#[derive(Debug)]
+pub struct Minimap {
+    pub enabled: bool,
+    /// Length of the longer side of the thumbnail, in logical pixels.
+    pub size: f32,
+    /// Distance to the lower right corner of the pane, in logical pixels.
+    pub margin: f32,
+    // The pane whose minimap is being dragged.
+    dragging: Option<usize>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct MinimapUniforms {
+    /// The visible region in texture coordinates, as (left, top, right, bottom).
+    region: [f32; 4],
+    /// Width of the outline in physical pixels.
+    border: f32,
+    _padding: [f32; 3],
+}
+unsafe impl bytemuck::Pod for MinimapUniforms {}
+unsafe impl bytemuck::Zeroable for MinimapUniforms {}
+
+impl Minimap {
+    pub fn new() -> Self {
+        Minimap {
+            enabled: false,
+            size: 160.0,
+            margin: 12.0,
+            dragging: None,
+        }
+    }
+
+    /// Where the thumbnail of an image goes in the viewport of a pane, in
+    /// physical pixels. None if the minimap is off or doesn't fit.
+    pub fn rect(&self, viewport: Rect, image_size: (f32, f32), scale_factor: f32) -> Option<Rect> {
+        if !self.enabled || image_size.0 <= 0.0 || image_size.1 <= 0.0 {
+            return None;
+        }
+        let scale = self.size * scale_factor / image_size.0.max(image_size.1);
+        let width = (image_size.0 * scale).round().max(1.0) as u32;
+        let height = (image_size.1 * scale).round().max(1.0) as u32;
+        let margin = (self.margin * scale_factor).round() as u32;
+        if width + 2 * margin > viewport.width || height + 2 * margin > viewport.height {
+            return None;
+        }
+        Some(Rect::new(
+            viewport.x + viewport.width - width - margin,
+            viewport.y + viewport.height - height - margin,
+            width,
+            height,
+        ))
+    }
+
+    /// The image position (in image pixels) under a canvas position, clamped to the image.
+    pub fn to_image(rect: &Rect, image_size: (f32, f32), pos: (f32, f32)) -> (f32, f32) {
+        let local = rect.to_local(pos);
+        (
+            (local.0 / rect.width as f32).max(0.0).min(1.0) * image_size.0,
+            (local.1 / rect.height as f32).max(0.0).min(1.0) * image_size.1,
+        )
+    }
+
+    pub fn grab(&mut self, pane: usize) {
+        self.dragging = Some(pane);
+    }
+
+    pub fn dragging(&self) -> Option<usize> {
+        self.dragging
+    }
+
+    pub fn release(&mut self) {
+        self.dragging = None;
+    }
+
+    /// `region` is the visible part of the image as (x, y, width, height),
+    /// relative to the image size.
+    pub fn uniforms(region: (f32, f32, f32, f32), scale_factor: f32) -> MinimapUniforms {
+        MinimapUniforms {
+            region: [region.0, region.1, region.0 + region.2, region.1 + region.3],
+            border: scale_factor.max(1.0),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Minimap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placed_in_lower_right_corner() {
+        let mut minimap = Minimap::new();
+        let viewport = Rect::new(100, 0, 400, 300);
+        assert_eq!(minimap.rect(viewport, (200.0, 100.0), 1.0), None);
+
+        minimap.enabled = true;
+        let rect = minimap.rect(viewport, (200.0, 100.0), 2.0).unwrap();
+        assert_eq!(
+            rect,
+            Rect::new(100 + 400 - 320 - 24, 300 - 160 - 24, 320, 160)
+        );
+        assert_eq!(
+            Minimap::to_image(&rect, (200.0, 100.0), (156.0 + 160.0, 116.0 + 200.0)),
+            (100.0, 100.0)
+        );
+        // Too small a pane has no minimap.
+        assert_eq!(
+            minimap.rect(Rect::new(0, 0, 300, 300), (200.0, 100.0), 2.0),
+            None
+        );
+    }
+}
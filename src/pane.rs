@@ -0,0 +1,98 @@
+use crate::{
+    animation::ViewAnimation,
+    layout::Rect,
+    minimap::{Minimap, MinimapUniforms},
+    vertex::{Quad, TransformBuffer},
+    view_state::ViewState,
+};
+use std::mem;
+
+/// One viewport of the canvas, showing a single image with its own view.
+pub struct Pane {
+    pub quad: Quad,
+    pub view: ViewState,
+    pub viewport: Rect,
+    /// Index of the image slot shown in this pane.
+    pub image: Option<usize>,
+    /// The transform of the image quad.
+    pub transform: TransformBuffer,
+    pub bind_group: Option<wgpu::BindGroup>,
+    /// The same bindings with nearest neighbor sampling.
+    pub nearest_bind_group: Option<wgpu::BindGroup>,
+    /// A running transition of the view.
+    pub animation: Option<ViewAnimation>,
+    /// The whole image fitted in the minimap.
+    pub minimap_quad: Quad,
+    pub minimap_transform: TransformBuffer,
+    pub minimap_buffer: wgpu::Buffer,
+}
+
+impl Pane {
+    pub fn new(
+        device: &wgpu::Device,
+        transform_layout: &wgpu::BindGroupLayout,
+        viewport: Rect,
+    ) -> Self {
+        let quad = Quad::with_init(viewport.size());
+        let minimap_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("MinimapUniforms"),
+            size: mem::size_of::<MinimapUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        Pane {
+            quad,
+            view: ViewState::new(),
+            viewport,
+            image: None,
+            transform: TransformBuffer::new(device, transform_layout),
+            bind_group: None,
+            nearest_bind_group: None,
+            animation: None,
+            minimap_quad: Quad::new(),
+            minimap_transform: TransformBuffer::new(device, transform_layout),
+            minimap_buffer,
+        }
+    }
+
+    pub fn set_image_size(&mut self, size: (f32, f32)) {
+        self.quad.map_texture_coords(size, size);
+        self.minimap_quad.map_texture_coords(size, size);
+    }
+
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+        self.quad.set_viewport_size(viewport.size());
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.bind_group.is_some() && self.viewport.width > 0 && self.viewport.height > 0
+    }
+
+    pub fn update_transform(&self, queue: &wgpu::Queue) {
+        self.transform
+            .write(queue, self.quad.transform_uniforms(&self.view));
+    }
+
+    /// Fit the image into a minimap of the given size and outline the visible region.
+    pub fn update_minimap(&mut self, queue: &wgpu::Queue, size: (f32, f32), scale_factor: f32) {
+        self.minimap_quad.set_viewport_size(size);
+        self.minimap_transform.write(
+            queue,
+            self.minimap_quad.transform_uniforms(&ViewState::new()),
+        );
+        let (x, y, width, height) = self.quad.visible_region(&self.view);
+        let image_size = self.quad.image_size();
+        let region = (
+            x / image_size.0,
+            y / image_size.1,
+            width / image_size.0,
+            height / image_size.1,
+        );
+        queue.write_buffer(
+            &self.minimap_buffer,
+            0,
+            bytemuck::cast_slice(&[Minimap::uniforms(region, scale_factor)]),
+        );
+    }
+}
@@ -0,0 +1,617 @@
+use crate::filters::build_pipeline;
+use crate::shaders::{fullscreen_vertex_shader, raw_fragment_shader};
+use crate::texture::ImageTexture;
+use std::mem;
+
+/// The raw bytes are uploaded as rows of this many texels, the largest
+/// texture width that is always supported.
+pub const RAW_ROW_BYTES: u32 = 8192;
+
+/// Order of the colors in the 2x2 cells of a Bayer mosaic, row by row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BayerPattern {
+    Rggb,
+    Grbg,
+    Gbrg,
+    Bggr,
+}
+
+impl BayerPattern {
+    // The index of the red sample in the cell, x + 2 * y. Blue is diagonally
+    // opposite. Must match shaders/raw.frag
+    fn red_index(&self) -> u32 {
+        match self {
+            BayerPattern::Rggb => 0,
+            BayerPattern::Grbg => 1,
+            BayerPattern::Gbrg => 2,
+            BayerPattern::Bggr => 3,
+        }
+    }
+}
+
+/// The layout of the pixels of a raw buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    Gray8,
+    Gray16,
+    Rgb8,
+    Rgba8,
+    Bgra8,
+    /// One sample per pixel, the colors of a Bayer color filter.
+    Bayer8(BayerPattern),
+    Bayer16(BayerPattern),
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::Gray8 | PixelFormat::Bayer8(_) => 1,
+            PixelFormat::Gray16 | PixelFormat::Bayer16(_) => 2,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+        }
+    }
+
+    /// 16-bit formats keep their raw values, the others are taken to be sRGB.
+    pub fn is_raw(&self) -> bool {
+        matches!(self, PixelFormat::Gray16 | PixelFormat::Bayer16(_))
+    }
+
+    fn pattern(&self) -> Option<BayerPattern> {
+        match *self {
+            PixelFormat::Bayer8(pattern) | PixelFormat::Bayer16(pattern) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    // Must match the constants in shaders/raw.frag
+    fn shader_mode(&self) -> u32 {
+        match self {
+            PixelFormat::Gray8 => 0,
+            PixelFormat::Gray16 => 1,
+            PixelFormat::Rgb8 => 2,
+            PixelFormat::Rgba8 => 3,
+            PixelFormat::Bgra8 => 4,
+            PixelFormat::Bayer8(_) => 5,
+            PixelFormat::Bayer16(_) => 6,
+        }
+    }
+}
+
+impl std::str::FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bayer = |pattern: &str| match pattern {
+            "rggb" => Some(BayerPattern::Rggb),
+            "grbg" => Some(BayerPattern::Grbg),
+            "gbrg" => Some(BayerPattern::Gbrg),
+            "bggr" => Some(BayerPattern::Bggr),
+            _ => None,
+        };
+        match s {
+            "gray8" => Ok(PixelFormat::Gray8),
+            "gray16" => Ok(PixelFormat::Gray16),
+            "rgb8" => Ok(PixelFormat::Rgb8),
+            "rgba8" => Ok(PixelFormat::Rgba8),
+            "bgra8" => Ok(PixelFormat::Bgra8),
+            // e.g. "bayer_rggb8" or "bayer_bggr16"
+            _ => {
+                let pattern = s.get(6..10).filter(|_| s.starts_with("bayer_"));
+                match (pattern.and_then(bayer), s.get(10..)) {
+                    (Some(pattern), Some("8")) => Ok(PixelFormat::Bayer8(pattern)),
+                    (Some(pattern), Some("16")) => Ok(PixelFormat::Bayer16(pattern)),
+                    _ => Err(format!("Unknown pixel format: {}", s)),
+                }
+            }
+        }
+    }
+}
+
+/// Byte order of 16-bit samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl std::str::FromStr for Endianness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "little" => Ok(Endianness::Little),
+            "big" => Ok(Endianness::Big),
+            _ => Err(format!("Unknown endianness: {}", s)),
+        }
+    }
+}
+
+/// A headerless buffer of pixels, kept as is. The conversion to RGBA is done
+/// on the GPU, `pixel` does the same on the CPU for probing.
+pub struct RawImage {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    /// Bytes from the start of one row to the next.
+    pub stride: u32,
+    pub endianness: Endianness,
+}
+
+impl RawImage {
+    /// A stride of 0 means the rows are packed.
+    pub fn new(
+        mut bytes: Vec<u8>,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        stride: u32,
+        endianness: Endianness,
+    ) -> Result<Self, String> {
+        if width == 0 || height == 0 {
+            return Err("Raw image without pixels".to_string());
+        }
+        if width > RAW_ROW_BYTES || height > RAW_ROW_BYTES {
+            return Err(format!(
+                "Raw image of {}x{} pixels, at most {} per side",
+                width, height, RAW_ROW_BYTES
+            ));
+        }
+        if format.pattern().is_some() && (width < 2 || height < 2) {
+            return Err("Bayer images must be at least 2x2 pixels".to_string());
+        }
+        let row = width
+            .checked_mul(format.bytes_per_pixel())
+            .ok_or("Raw row too large")?;
+        let stride = if stride == 0 { row } else { stride };
+        if stride < row {
+            return Err(format!(
+                "Stride {} is less than a row of {} bytes",
+                stride, row
+            ));
+        }
+        let needed = (stride as u64)
+            .checked_mul(height as u64 - 1)
+            .and_then(|n| n.checked_add(row as u64))
+            .ok_or("Raw buffer too large")?;
+        if needed > RAW_ROW_BYTES as u64 * RAW_ROW_BYTES as u64 {
+            return Err("Raw buffer too large".to_string());
+        }
+        if (bytes.len() as u64) < needed {
+            return Err(format!(
+                "Raw buffer of {} bytes, {} needed",
+                bytes.len(),
+                needed
+            ));
+        }
+        // Trailing bytes past the last row are not part of the image.
+        bytes.truncate(needed as usize);
+        Ok(RawImage {
+            bytes,
+            width,
+            height,
+            format,
+            stride,
+            endianness,
+        })
+    }
+
+    // Sample i of the pixel at (x, y), 8-bit samples in 0..1 and 16-bit as stored.
+    fn sample(&self, x: u32, y: u32, i: u32) -> f32 {
+        let offset = (y * self.stride + x * self.format.bytes_per_pixel()) as usize;
+        if self.format.bytes_per_pixel() == 2 {
+            let b = [self.bytes[offset], self.bytes[offset + 1]];
+            match self.endianness {
+                Endianness::Little => u16::from_le_bytes(b) as f32,
+                Endianness::Big => u16::from_be_bytes(b) as f32,
+            }
+        } else {
+            self.bytes[offset + i as usize] as f32 / 255.0
+        }
+    }
+
+    // Out of range positions are mirrored, which keeps the color of the mosaic.
+    fn mosaic(&self, x: i64, y: i64) -> f32 {
+        let mirror = |v: i64, size: u32| {
+            let last = size as i64 - 1;
+            v.abs().min(2 * last - v.abs()) as u32
+        };
+        self.sample(mirror(x, self.width), mirror(y, self.height), 0)
+    }
+
+    // Same as demosaic in shaders/raw.frag
+    fn demosaic(&self, x: u32, y: u32, pattern: BayerPattern) -> [f32; 3] {
+        let color_at = |x: i64, y: i64| {
+            let index = ((x & 1) | ((y & 1) << 1)) as u32;
+            if index == pattern.red_index() {
+                0
+            } else if index == pattern.red_index() ^ 3 {
+                2
+            } else {
+                1
+            }
+        };
+        let (x, y) = (x as i64, y as i64);
+        let m = |dx: i64, dy: i64| self.mosaic(x + dx, y + dy);
+        let center = m(0, 0);
+        let across = (m(-1, 0) + m(1, 0) + m(0, -1) + m(0, 1)) / 4.0;
+        let diagonal = (m(-1, -1) + m(1, -1) + m(-1, 1) + m(1, 1)) / 4.0;
+        let horizontal = (m(-1, 0) + m(1, 0)) / 2.0;
+        let vertical = (m(0, -1) + m(0, 1)) / 2.0;
+        match color_at(x, y) {
+            0 => [center, across, diagonal],
+            2 => [diagonal, across, center],
+            _ if color_at(x + 1, y) == 0 => [horizontal, center, vertical],
+            _ => [vertical, center, horizontal],
+        }
+    }
+
+    /// The RGBA value of a pixel as converted on the GPU, before decoding sRGB.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[f32; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let s = |i| self.sample(x, y, i);
+        Some(match self.format {
+            PixelFormat::Gray8 | PixelFormat::Gray16 => [s(0), s(0), s(0), 1.0],
+            PixelFormat::Rgb8 => [s(0), s(1), s(2), 1.0],
+            PixelFormat::Rgba8 => [s(0), s(1), s(2), s(3)],
+            PixelFormat::Bgra8 => [s(2), s(1), s(0), s(3)],
+            PixelFormat::Bayer8(pattern) | PixelFormat::Bayer16(pattern) => {
+                let [r, g, b] = self.demosaic(x, y, pattern);
+                [r, g, b, 1.0]
+            }
+        })
+    }
+
+    /// The lowest and highest first sample of the pixels, the range of the
+    /// values of 16-bit formats.
+    pub fn range(&self) -> (f32, f32) {
+        let mut range = (f32::MAX, f32::MIN);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let v = self.sample(x, y, 0);
+                range = (range.0.min(v), range.1.max(v));
+            }
+        }
+        range
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct RawUniforms {
+    format: u32,
+    pattern: u32,
+    big_endian: u32,
+    stride: u32,
+    width: u32,
+    height: u32,
+    _padding: [u32; 2],
+}
+unsafe impl bytemuck::Pod for RawUniforms {}
+unsafe impl bytemuck::Zeroable for RawUniforms {}
+
+/// Converts raw images to textures for the image shaders with a full-screen
+/// pass over the new texture.
+pub struct RawConverter {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Writes linear values to sRGB textures, for 8-bit formats.
+    srgb_pipeline: wgpu::RenderPipeline,
+    /// Writes raw values to float textures, for 16-bit formats.
+    float_pipeline: wgpu::RenderPipeline,
+}
+
+impl RawConverter {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("RawBindGroupLayout"),
+            bindings: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Uint,
+                    },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: wgpu::CompareFunction::Undefined,
+            anisotropy_clamp: 1,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            label: Some("RawSampler"),
+        });
+        let vertex = fullscreen_vertex_shader(device);
+        let fragment = raw_fragment_shader(device);
+        let pipeline =
+            |format| build_pipeline(device, &pipeline_layout, &vertex, &fragment, format);
+        RawConverter {
+            srgb_pipeline: pipeline(wgpu::TextureFormat::Rgba8UnormSrgb),
+            float_pipeline: pipeline(wgpu::TextureFormat::Rgba32Float),
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Upload the bytes of a raw image and convert them to an image texture:
+    /// Rgba8UnormSrgb for 8-bit formats and Rgba32Float with a window over
+    /// the range of the samples for 16-bit formats.
+    pub fn convert(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: RawImage,
+    ) -> ImageTexture {
+        // Rows of RAW_ROW_BYTES texels, the last one padded.
+        let rows = (image.bytes.len() as u32 + RAW_ROW_BYTES - 1) / RAW_ROW_BYTES;
+        let mut bytes = image.bytes.clone();
+        bytes.resize((rows * RAW_ROW_BYTES) as usize, 0);
+        let size = wgpu::Extent3d {
+            width: RAW_ROW_BYTES,
+            height: rows,
+            depth: 1,
+        };
+        let source = device.create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Uint,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            label: Some("RawTexture"),
+        });
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &source,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &bytes,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: RAW_ROW_BYTES,
+                rows_per_image: rows,
+            },
+            size,
+        );
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            format: wgpu::TextureFormat::R8Uint,
+            dimension: wgpu::TextureViewDimension::D2,
+            aspect: wgpu::TextureAspect::default(),
+            base_mip_level: 0,
+            base_array_layer: 0,
+            level_count: 1,
+            array_layer_count: 1,
+        });
+
+        let uniforms = RawUniforms {
+            format: image.format.shader_mode(),
+            pattern: image.format.pattern().map_or(0, |p| p.red_index()),
+            big_endian: (image.endianness == Endianness::Big) as u32,
+            stride: image.stride,
+            width: image.width,
+            height: image.height,
+            _padding: [0; 2],
+        };
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("RawUniforms"),
+            size: mem::size_of::<RawUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        queue.write_buffer(&buffer, 0, bytemuck::bytes_of(&uniforms));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("RawBindGroup"),
+            layout: &self.bind_group_layout,
+            bindings: &[
+                wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::Binding {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                },
+            ],
+        });
+
+        let (format, pipeline) = if image.format.is_raw() {
+            (wgpu::TextureFormat::Rgba32Float, &self.float_pipeline)
+        } else {
+            (wgpu::TextureFormat::Rgba8UnormSrgb, &self.srgb_pipeline)
+        };
+        let (texture, view) =
+            ImageTexture::create_attachment(device, (image.width, image.height), format);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("RawConversion"),
+        });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &view,
+                    resolve_target: None,
+                    load_op: wgpu::LoadOp::Clear,
+                    clear_color: wgpu::Color::BLACK,
+                    store_op: wgpu::StoreOp::Store,
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        ImageTexture::from_converted(device, queue, (texture, view), image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Interpolation;
+    use crate::renderer::State;
+    use crate::view_state::Zoom;
+
+    // Rows of the readback buffer must be a multiple of 256 bytes.
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 32;
+
+    #[test]
+    fn formats() {
+        let bgra = RawImage::new(
+            vec![1, 2, 3, 255, 0, 0, 0, 0],
+            1,
+            2,
+            PixelFormat::Bgra8,
+            4,
+            Endianness::Little,
+        )
+        .unwrap();
+        assert_eq!(
+            bgra.pixel(0, 0),
+            Some([3.0 / 255.0, 2.0 / 255.0, 1.0 / 255.0, 1.0])
+        );
+
+        // Rows of 2 pixels padded to 6 bytes.
+        let bytes = vec![0x12, 0x34, 0, 1, 0xff, 0xff, 0, 2, 0, 3];
+        let gray = RawImage::new(bytes, 2, 2, PixelFormat::Gray16, 6, Endianness::Big).unwrap();
+        assert_eq!(gray.pixel(0, 0).unwrap()[0], 0x1234 as f32);
+        assert_eq!(gray.pixel(1, 1).unwrap()[0], 3.0);
+        assert_eq!(gray.range(), (1.0, 0x1234 as f32));
+        assert!(RawImage::new(vec![0; 9], 2, 2, PixelFormat::Gray16, 6, Endianness::Big).is_err());
+        let wide = RawImage::new(vec![0; 16], 8193, 1, PixelFormat::Gray8, 0, Endianness::Big);
+        assert!(wide.is_err());
+        let extra = RawImage::new(vec![0; 16], 2, 2, PixelFormat::Gray8, 0, Endianness::Big);
+        assert_eq!(extra.unwrap().bytes.len(), 4);
+
+        assert_eq!(
+            "bayer_gbrg16".parse::<PixelFormat>(),
+            Ok(PixelFormat::Bayer16(BayerPattern::Gbrg))
+        );
+        assert!("bayer_rgbg8".parse::<PixelFormat>().is_err());
+    }
+
+    #[test]
+    fn demosaic_keeps_flat_colors() {
+        let (r, g, b) = (40u8, 120u8, 200u8);
+        for &pattern in &[
+            BayerPattern::Rggb,
+            BayerPattern::Grbg,
+            BayerPattern::Gbrg,
+            BayerPattern::Bggr,
+        ] {
+            let name = format!("{:?}", pattern).to_lowercase();
+            let mut bytes = Vec::new();
+            for y in 0..5 {
+                for x in 0..6 {
+                    let color = name.as_bytes()[(x % 2 + 2 * (y % 2)) as usize];
+                    bytes.push(match color {
+                        b'r' => r,
+                        b'g' => g,
+                        _ => b,
+                    });
+                }
+            }
+            let format = PixelFormat::Bayer8(pattern);
+            let image = RawImage::new(bytes, 6, 5, format, 0, Endianness::Little).unwrap();
+            for y in 0..5 {
+                for x in 0..6 {
+                    let p = image.pixel(x, y).unwrap();
+                    let to_u8 = |v: f32| (v * 255.0).round() as u8;
+                    assert_eq!(
+                        [to_u8(p[0]), to_u8(p[1]), to_u8(p[2])],
+                        [r, g, b],
+                        "{:?} at ({}, {})",
+                        pattern,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gpu_matches_reference() {
+        let mut state = match State::headless((WIDTH, HEIGHT)) {
+            Some(state) => state,
+            None => return,
+        };
+        state.set_interpolation(Interpolation::Nearest);
+        let cases = [
+            (PixelFormat::Rgb8, WIDTH * 3 + 5),
+            (PixelFormat::Bgra8, 0),
+            (PixelFormat::Bayer8(BayerPattern::Grbg), 0),
+            (PixelFormat::Bayer8(BayerPattern::Bggr), WIDTH + 3),
+        ];
+        for &(format, stride) in &cases {
+            let row = WIDTH * format.bytes_per_pixel();
+            let stride = stride.max(row);
+            // Opaque alpha for the BGRA case.
+            let bytes: Vec<u8> = (0..stride * HEIGHT)
+                .map(|i| {
+                    if format == PixelFormat::Bgra8 && i % 4 == 3 {
+                        255
+                    } else {
+                        ((i * 7919) % 251) as u8
+                    }
+                })
+                .collect();
+            state
+                .load_raw(0, &bytes, WIDTH, HEIGHT, format, stride, Endianness::Little)
+                .unwrap();
+            state.set_zoom_mode(Zoom::Pixel(1.0), false);
+            let pixels = state.render_pixels();
+
+            let image =
+                RawImage::new(bytes, WIDTH, HEIGHT, format, stride, Endianness::Little).unwrap();
+            for (i, gpu) in pixels.chunks(4).enumerate() {
+                let (x, y) = (i as u32 % WIDTH, i as u32 / WIDTH);
+                let cpu = image.pixel(x, y).unwrap();
+                for (c, (&gpu, &cpu)) in gpu.iter().zip(cpu.iter()).take(3).enumerate() {
+                    let expected = (cpu * 255.0).round() as i32;
+                    assert!(
+                        (gpu as i32 - expected).abs() <= 2,
+                        "{:?} at ({}, {}) channel {}: {} != {}",
+                        format,
+                        x,
+                        y,
+                        c,
+                        gpu,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+}
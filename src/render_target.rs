@@ -102,21 +102,23 @@ impl TextureTarget {
         }
     }
 
-    // pub async fn get_buffer(&self, device: &wgpu::Device) -> Vec<u8> {
-    //     let output_buffer = self.output_buffer.as_ref().unwrap();
-    //     let out;
-    //     {
-    //         let slice = output_buffer.slice(..);
-    //         let map = slice.map_async(wgpu::MapMode::Read);
-    //         // Wait for the buffer to be mapped.
-    //         device.poll(wgpu::Maintain::Wait);
-    //         let _ = map.await.unwrap(); // Wait for the future to be ready
-    //         let view = slice.get_mapped_range();
-    //         out = Vec::from(&*view);
-    //     }
-    //     output_buffer.unmap();
-    //     out
-    // }
+    /// Read back what `on_render` copied, the size of the buffer must be a
+    /// multiple of 256 bytes per row.
+    pub async fn get_buffer(&self, device: &wgpu::Device) -> Vec<u8> {
+        let output_buffer = self.output_buffer.as_ref().unwrap();
+        let out;
+        {
+            let slice = output_buffer.slice(..);
+            let map = slice.map_async(wgpu::MapMode::Read);
+            // Wait for the buffer to be mapped.
+            device.poll(wgpu::Maintain::Wait);
+            map.await.expect("Failed to map the output buffer");
+            let view = slice.get_mapped_range();
+            out = Vec::from(&*view);
+        }
+        output_buffer.unmap();
+        out
+    }
 }
 
 impl RenderTarget for TextureTarget {
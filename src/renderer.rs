@@ -1,12 +1,35 @@
 use crate::{
+    animation::{Easing, ViewAnimation, TRANSITION_DURATION},
+    color::{self, DisplayTransform, ToneMap},
+    compare::{Compare, CompareMode, CompareUniforms},
+    convolution::BuiltinFilter,
+    dicom::{self, DicomFrames},
+    display::{AlphaMode, Channel, DisplaySettings, DisplayUniforms, Interpolation},
+    filters::{FilterChain, SCENE_FORMAT},
+    frames::{Frame, FrameSource, Playback},
+    hdr,
+    history::History,
+    kinetic::{Inertia, VelocityTracker},
+    layout::{Layout, Rect, ViewLink},
+    loupe::{Loupe, LoupeUniforms},
+    minimap::Minimap,
+    pane::Pane,
+    raw::{Endianness, PixelFormat, RawConverter, RawImage},
     render_target::{RenderTarget, TextureTarget},
-    vertex::{Quad, Vertex},
-    view_state::ViewState,
+    shaders::{Blend, FragmentShader, PipelineCache, PipelineKey},
+    sync::{SyncSpace, ViewChange, ViewSnapshot},
+    texture::{ImageTexture, Pixels, WindowLevel},
+    tiff_frames::{self, TiffFrames},
+    vertex::{Quad, TransformBuffer, Vertex},
+    view_state::{SavedView, ViewConstraints, ViewState, Zoom},
 };
 use std::io::prelude::*;
-use std::{mem, path::Path};
+use std::{collections::HashMap, mem, path::Path};
 //use wgpu::util::DeviceExt;
 
+// Number of view changes that can be undone.
+const HISTORY_SIZE: usize = 64;
+
 pub struct State<T>
 where
     T: RenderTarget,
@@ -14,19 +37,51 @@ where
     target: T,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    // Size of the render target in physical pixels.
     size: (u32, u32),
-    clear_color: wgpu::Color,
-    render_pipeline: wgpu::RenderPipeline,
+    // Physical pixels per logical pixel.
+    scale_factor: f64,
+    pipelines: PipelineCache,
+    // Post-processing of the rendered canvas, ending with the display transform.
+    filters: FilterChain,
+    display_transform: DisplayTransform,
+    // The unit quad shared by all draws, placed by the transform in set 1.
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
-    texture: wgpu::Texture,
-    texture_view: wgpu::TextureView,
     texture_sampler: wgpu::Sampler,
-    texture_bind_group: wgpu::BindGroup,
+    // Used instead of texture_sampler at high magnification.
+    nearest_sampler: wgpu::Sampler,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    transform_bind_group_layout: wgpu::BindGroupLayout,
+    images: Vec<Option<ImageTexture>>,
+    // The frame sequences shown in image slots, by slot.
+    playbacks: HashMap<usize, Playback>,
+    raw_converter: RawConverter,
     current_image_no: u8,
-    quad: Quad,
+    layout: Layout,
+    view_link: ViewLink,
+    panes: Vec<Pane>,
+    // The pane that keyboard and API commands apply to.
+    active_pane: usize,
+    // The pane receiving the current pan/zoom interaction.
+    interaction_pane: Option<usize>,
+    compare: Compare,
+    compare_buffer: wgpu::Buffer,
+    display: DisplaySettings,
+    display_buffer: wgpu::Buffer,
+    minimap: Minimap,
+    loupe: Loupe,
+    loupe_buffer: wgpu::Buffer,
+    // The magnified view of the pane under the loupe.
+    loupe_quad: Quad,
+    loupe_transform: TransformBuffer,
+    // Snapshots of the views of all panes.
+    history: History<Vec<ViewState>>,
+    constraints: ViewConstraints,
+    pointer_velocity: VelocityTracker,
+    // Panning that continues after a drag, in the pane with the given index.
+    inertia: Option<(usize, Inertia)>,
     dirty: bool,
-    view: ViewState,
     // brush: wgpu_glyph::GlyphBrush<()>,
     // belt: wgpu::util::StagingBelt,
     status_message: Option<String>,
@@ -36,7 +91,14 @@ impl<T> State<T>
 where
     T: RenderTarget,
 {
-    pub async fn new(instance: wgpu::Instance, size: (u32, u32), mut target: T) -> Self {
+    /// Create the state for a target of `size` physical pixels. Input positions
+    /// are given in logical pixels, multiplied by `scale_factor` to get physical pixels.
+    pub async fn new(
+        instance: wgpu::Instance,
+        size: (u32, u32),
+        scale_factor: f64,
+        mut target: T,
+    ) -> Self {
         let adapter = instance
             .request_adapter(
                 &wgpu::RequestAdapterOptions {
@@ -78,20 +140,49 @@ where
 
         log::info!("RenderTarget created");
 
-        let (texture, texture_view, texture_sampler, texture_bind_group, texture_bind_group_layput) =
+        let (texture_sampler, nearest_sampler, texture_bind_group_layout) =
             Self::create_texture(&device);
+        let transform_bind_group_layout = TransformBuffer::create_layout(&device);
+        let bind_group_layouts = [&texture_bind_group_layout, &transform_bind_group_layout];
 
         log::info!("Texture created");
 
-        let render_pipeline =
-            Self::build_render_pipeline(&device, target.format(), texture_bind_group_layput);
+        // The pipelines are built when they are first used.
+        // The scene is drawn in linear light, the filter chain ends with the
+        // display transform into the render target.
+        let pipelines = PipelineCache::new(&device, SCENE_FORMAT, &bind_group_layouts);
+        let filters = FilterChain::new(&device, &queue, target.format(), size);
+        let raw_converter = RawConverter::new(&device);
+
+        let compare_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("CompareUniforms"),
+            size: mem::size_of::<CompareUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let display_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("DisplayUniforms"),
+            size: mem::size_of::<DisplayUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let loupe_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LoupeUniforms"),
+            size: mem::size_of::<LoupeUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
 
         log::info!("Pipeline created");
 
         // Build the "model" we will use
-        let quad = Quad::with_init((size.0 as f32, size.1 as f32));
-        log::info!("Quad created");
-        let (vertex_buffer, index_buffer) = Self::build_vertex_buffer(&device, &queue, &quad);
+        let layout = Layout::Single;
+        let panes: Vec<_> = layout
+            .viewports(size)
+            .into_iter()
+            .map(|viewport| Pane::new(&device, &transform_bind_group_layout, viewport))
+            .collect();
+        log::info!("Panes created");
+        let vertex_buffer = Self::build_vertex_buffer(&device, &queue, &panes[0].quad);
+        let index_buffer = Self::build_index_buffer(&device, &queue, &panes[0].quad);
+        let loupe_transform = TransformBuffer::new(&device, &transform_bind_group_layout);
 
         log::info!("Quad and buffers created");
 
@@ -117,18 +208,39 @@ where
             // sc_desc,
             // swap_chain,
             size,
-            clear_color: wgpu::Color::BLACK,
-            render_pipeline,
+            scale_factor,
+            pipelines,
+            filters,
+            display_transform: DisplayTransform::new(),
             vertex_buffer,
             index_buffer,
-            texture,
-            texture_view,
             texture_sampler,
-            texture_bind_group,
+            nearest_sampler,
+            texture_bind_group_layout,
+            transform_bind_group_layout,
+            images: Vec::new(),
+            playbacks: HashMap::new(),
+            raw_converter,
             current_image_no: 0,
-            quad,
+            layout,
+            view_link: ViewLink::Independent,
+            panes,
+            active_pane: 0,
+            interaction_pane: None,
+            compare: Compare::new(),
+            compare_buffer,
+            display: DisplaySettings::new(),
+            display_buffer,
+            minimap: Minimap::new(),
+            loupe: Loupe::new(),
+            loupe_buffer,
+            loupe_quad: Quad::new(),
+            loupe_transform,
+            history: History::new(HISTORY_SIZE),
+            constraints: ViewConstraints::new(),
+            pointer_velocity: VelocityTracker::new(),
+            inertia: None,
             dirty: true,
-            view: ViewState::new(),
             // brush,
             // belt,
             status_message: None,
@@ -137,43 +249,7 @@ where
 
     fn create_texture(
         device: &wgpu::Device,
-    ) -> (
-        wgpu::Texture,
-        wgpu::TextureView,
-        wgpu::Sampler,
-        wgpu::BindGroup,
-        wgpu::BindGroupLayout,
-    ) {
-        let texture_size = wgpu::Extent3d {
-            width: 1024,
-            height: 1024,
-            depth: 1,
-        };
-
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            //format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-            label: Some("MyTexture"),
-        });
-
-        // Create a texture view
-        //let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
-            label: None,
-            //format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            dimension: wgpu::TextureViewDimension::D2,
-            aspect: wgpu::TextureAspect::default(),
-            base_mip_level: 0,
-            base_array_layer: 0,
-            level_count: 1,
-            array_layer_count: 1,
-        });
+    ) -> (wgpu::Sampler, wgpu::Sampler, wgpu::BindGroupLayout) {
         let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -187,9 +263,26 @@ where
             mipmap_filter: wgpu::FilterMode::Nearest,
             label: Some("MySampler"),
         });
+        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: wgpu::CompareFunction::Undefined,
+            anisotropy_clamp: 1,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            label: Some("NearestSampler"),
+        });
 
-        // Create a bind group for the texture.
-        let texture_bind_group_layput =
+        // Create a bind group layout for the textures, each pane gets its own bind group.
+        // The second texture and the compare uniforms are only used by the compare shader,
+        // the display uniforms by the grid and image shaders and the per pane minimap
+        // uniforms by the minimap shader. The loupe uniforms are shared by all panes,
+        // the image uniforms with the window of raw images belong to the image.
+        let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("MyBindgroupLayout"),
                 //entries: &[
@@ -208,48 +301,121 @@ where
                         visibility: wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::Sampler { comparison: false },
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::SampledTexture {
+                            multisampled: false,
+                            dimension: wgpu::TextureViewDimension::D2,
+                            component_type: wgpu::TextureComponentType::Uint,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
                 ],
             });
 
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        (texture_sampler, nearest_sampler, texture_bind_group_layout)
+    }
+
+    fn create_bind_group(
+        &self,
+        texture_view: &wgpu::TextureView,
+        compare_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        minimap_buffer: &wgpu::Buffer,
+        image_buffer: &wgpu::Buffer,
+        compare_image_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("MyBindGroup"),
-            layout: &texture_bind_group_layput,
+            layout: &self.texture_bind_group_layout,
             //entries: &[
             bindings: &[
                 wgpu::Binding {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                    resource: wgpu::BindingResource::TextureView(texture_view),
                 },
                 wgpu::Binding {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture_sampler),
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::Binding {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(compare_view),
+                },
+                wgpu::Binding {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(self.compare_buffer.slice(..)),
+                },
+                wgpu::Binding {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Buffer(self.display_buffer.slice(..)),
+                },
+                wgpu::Binding {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Buffer(minimap_buffer.slice(..)),
+                },
+                wgpu::Binding {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Buffer(self.loupe_buffer.slice(..)),
+                },
+                wgpu::Binding {
+                    binding: 7,
+                    resource: wgpu::BindingResource::Buffer(image_buffer.slice(..)),
+                },
+                wgpu::Binding {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Buffer(compare_image_buffer.slice(..)),
                 },
             ],
-        });
-
-        (
-            texture,
-            texture_view,
-            texture_sampler,
-            texture_bind_group,
-            texture_bind_group_layput,
-        )
+        })
     }
 
     fn build_vertex_buffer(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         quad: &Quad,
-    ) -> (wgpu::Buffer, wgpu::Buffer) {
+    ) -> wgpu::Buffer {
         let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Vbuf"),
-            size: quad.vertex_count() as u64 * mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            //mapped_at_creation: false,
             usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            size: quad.vertex_count() as u64 * mem::size_of::<Vertex>() as wgpu::BufferAddress,
         });
 
-        log::info!("Empty buffer created");
+        queue.write_buffer(&vertex_buffer, 0, bytemuck::cast_slice(quad.vertex_ref()));
+        vertex_buffer
+    }
 
+    fn build_index_buffer(device: &wgpu::Device, queue: &wgpu::Queue, quad: &Quad) -> wgpu::Buffer {
         // let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         //     label: Some("MyIndexBuffer"),
         //     contents: bytemuck::cast_slice(quad.index_ref()),
@@ -264,120 +430,103 @@ where
         queue.write_buffer(&index_buffer, 0, bytemuck::cast_slice(quad.index_ref()));
 
         log::info!("Init buffer created");
-        (vertex_buffer, index_buffer)
+        index_buffer
     }
 
-    // fn create_shader_from_file(device: &wgpu::Device, filename: &Path) -> wgpu::ShaderModule {
-    //     let buffer =
-    //         std::fs::read(filename).expect(&format!("Failed to read {}", filename.display()));
-    //     let shader_data = wgpu::util::make_spirv(&buffer);
-    //     device.create_shader_module(shader_data)
-    // }
-
-    // fn compile_shaders(device: &wgpu::Device) -> (wgpu::ShaderModule, wgpu::ShaderModule) {
-    //     let vs_module = Self::create_shader_from_file(device, Path::new("vert.spirv"));
-    //     let fs_module = Self::create_shader_from_file(device, Path::new("frag.spirv"));
-
-    //     (vs_module, fs_module)
-    // }
-
-    fn shaders_from_static(device: &wgpu::Device) -> (wgpu::ShaderModule, wgpu::ShaderModule) {
-        let vs_data = include_bytes!("../vert.spirv");
-        //let fs_data = include_bytes!("../frag.spirv");
-        let fs_data = include_bytes!("../frag_static.spirv");
-        let vs_module = device
-            .create_shader_module(&wgpu::read_spirv(std::io::Cursor::new(&vs_data[..])).unwrap());
-        let fs_module = device
-            .create_shader_module(&wgpu::read_spirv(std::io::Cursor::new(&fs_data[..])).unwrap());
+    pub fn resize(&mut self, new_size: (u32, u32)) {
+        self.size = (new_size.0, new_size.1);
+        self.target.create(&self.device, self.size);
+        self.filters.resize(&self.device, &self.queue, self.size);
+        self.update_viewports();
 
-        // let vs_module = device.create_shader_module(wgpu::include_spirv!("../vert.spirv"));
-        // let fs_module = device.create_shader_module(wgpu::include_spirv!("../frag.spirv"));
-        (vs_module, fs_module)
+        self.dirty = true;
     }
 
-    fn build_render_pipeline(
-        device: &wgpu::Device,
-        swap_texture_format: wgpu::TextureFormat,
-        texture_bind_group_layout: wgpu::BindGroupLayout,
-    ) -> wgpu::RenderPipeline {
-        // Compile the shaders
-        //let (vs_module, fs_module) = Self::compile_shaders(device);
-
-        // Use static shaders (i.e. included in the binary)
-        let (vs_module, fs_module) = Self::shaders_from_static(device);
-
-        log::info!("Shaders created");
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                bind_group_layouts: &[&texture_bind_group_layout],
-                // push_constant_ranges: &[],
-                // label: None,
-            });
+    /// Resize to a size in logical pixels.
+    pub fn resize_logical(&mut self, size: (f64, f64)) {
+        let physical = (
+            (size.0 * self.scale_factor).round() as u32,
+            (size.1 * self.scale_factor).round() as u32,
+        );
+        self.resize(physical);
+    }
 
-        log::info!("Pipeline Layout created");
+    pub fn logical_size(&self) -> (f64, f64) {
+        (
+            self.size.0 as f64 / self.scale_factor,
+            self.size.1 as f64 / self.scale_factor,
+        )
+    }
 
-        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            //label: None,
-            //layout: Some(&render_pipeline_layout),
-            layout: &render_pipeline_layout,
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                //clamp_depth: false,
-                depth_bias: 0,
-                depth_bias_clamp: 0.0,
-                depth_bias_slope_scale: 0.0,
-            }),
-            color_states: &[wgpu::ColorStateDescriptor {
-                format: swap_texture_format,
-                color_blend: wgpu::BlendDescriptor::REPLACE,
-                alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
-            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-            depth_stencil_state: None,
-            vertex_state: wgpu::VertexStateDescriptor {
-                index_format: wgpu::IndexFormat::Uint16,
-                vertex_buffers: &[Vertex::to_desc()],
-            },
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
-        })
+    /// Change the number of physical pixels per logical pixel, keeping the logical size.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        let logical = self.logical_size();
+        self.scale_factor = scale_factor;
+        self.resize_logical(logical);
     }
 
-    pub fn resize(&mut self, new_size: (u32, u32)) {
-        self.size = (new_size.0, new_size.1);
-        self.target.create(&self.device, self.size);
-        self.quad
-            .set_viewport_size((self.size.0 as f32, self.size.1 as f32));
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
 
-        self.dirty = true;
+    fn to_physical(&self, pos: (f32, f32)) -> (f32, f32) {
+        let scale = self.scale_factor as f32;
+        (pos.0 * scale, pos.1 * scale)
     }
 
-    fn update_vertex_buffer(&self) {
-        self.queue.write_buffer(
-            &self.vertex_buffer,
-            0,
-            bytemuck::cast_slice(&self.quad.get_vertex(&self.view)),
-        );
+    fn update_viewports(&mut self) {
+        let viewports = self.layout.viewports(self.size);
+        self.panes
+            .iter_mut()
+            .zip(viewports.into_iter())
+            .for_each(|(pane, viewport)| {
+                // Keep the same part of the image in view when the viewport changes size.
+                let saved = pane.quad.save_view(&pane.view);
+                pane.set_viewport(viewport);
+                pane.quad.restore_view(&saved, &mut pane.view);
+            });
+        self.constrain_all();
     }
 
     pub fn render(&mut self) {
 
         //log::info!("Render pos: {:?}", self.view.pos);
 
-        // Make sure the vertex buffer is updated before rendering.
-        self.update_vertex_buffer();
+        // Make sure the vertex buffers are updated before rendering.
+        for index in 0..self.panes.len() {
+            self.panes[index].update_transform(&self.queue);
+            if let Some(rect) = self.minimap_rect(index) {
+                let scale_factor = self.scale_factor as f32;
+                self.panes[index].update_minimap(&self.queue, rect.size(), scale_factor);
+            }
+        }
+        self.queue.write_buffer(
+            &self.compare_buffer,
+            0,
+            bytemuck::cast_slice(&[self.compare.uniforms(self.scale_factor as f32)]),
+        );
+        self.queue.write_buffer(
+            &self.display_buffer,
+            0,
+            bytemuck::cast_slice(&[self.display.uniforms(self.scale_factor as f32)]),
+        );
+        let loupe = self.update_loupe();
+        if let Some(pane) = self.panes.get(self.active_pane) {
+            let image_scale = pane.quad.image_scale(&pane.view);
+            self.filters.set_image_scale(&self.queue, image_scale);
+        }
+
+        let image_key = if self.compare.is_active() {
+            PipelineKey::new(FragmentShader::Compare, Blend::Replace)
+        } else {
+            PipelineKey::new(FragmentShader::Image, Blend::Replace)
+        };
+        let grid_key = PipelineKey::new(FragmentShader::Grid, Blend::Alpha);
+        let minimap_key = PipelineKey::new(FragmentShader::Minimap, Blend::Replace);
+        let loupe_key = PipelineKey::new(FragmentShader::Loupe, Blend::Replace);
+        for &key in [image_key, grid_key, minimap_key, loupe_key].iter() {
+            self.pipelines.prepare(&self.device, key);
+        }
 
         let render_target = self.target.output();
 
@@ -387,29 +536,139 @@ where
                 label: Some("Render Encoder"),
             });
 
+        // The images are filtered on their own, the background is added by
+        // the display transform.
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: render_target.view(),
+                    attachment: self.filters.scene_view(),
                     resolve_target: None,
                     load_op: wgpu::LoadOp::Clear,
-                    clear_color: self.clear_color,
+                    clear_color: wgpu::Color::TRANSPARENT,
                     store_op: wgpu::StoreOp::Store,
                     // ops: wgpu::Operations {
-                    //     load: wgpu::LoadOp::Clear(self.clear_color),
+                    //     load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
                     //     store: true,
                     // },
                 }],
                 depth_stencil_attachment: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+            let image_pipeline = self.pipelines.get(image_key);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..));
-            render_pass.draw_indexed(0..self.quad.index_count(), 0, 0..1);
+            for pane in self.panes.iter() {
+                if !pane.is_visible() {
+                    continue;
+                }
+                let scale = pane.quad.image_scale(&pane.view);
+                let vp = &pane.viewport;
+                render_pass.set_viewport(
+                    vp.x as f32,
+                    vp.y as f32,
+                    vp.width as f32,
+                    vp.height as f32,
+                    0.0,
+                    1.0,
+                );
+                render_pass.set_scissor_rect(vp.x, vp.y, vp.width, vp.height);
+                let bind_group = match &pane.nearest_bind_group {
+                    Some(nearest) if self.display.use_nearest(scale) => nearest,
+                    _ => pane.bind_group.as_ref().unwrap(),
+                };
+                render_pass.set_pipeline(image_pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.set_bind_group(1, &pane.transform.bind_group, &[]);
+                render_pass.draw_indexed(0..pane.quad.index_count(), 0, 0..1);
+            }
         }
 
+        self.filters.apply(&mut encoder);
+
+        // The overlays show the images as they are, on top of the filtered ones.
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: self.filters.result_view(),
+                    resolve_target: None,
+                    load_op: wgpu::LoadOp::Load,
+                    clear_color: wgpu::Color::TRANSPARENT,
+                    store_op: wgpu::StoreOp::Store,
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..));
+            for (index, pane) in self.panes.iter().enumerate() {
+                if !pane.is_visible() {
+                    continue;
+                }
+                let scale = pane.quad.image_scale(&pane.view);
+                if self.display.has_overlay(scale) {
+                    let vp = &pane.viewport;
+                    render_pass.set_viewport(
+                        vp.x as f32,
+                        vp.y as f32,
+                        vp.width as f32,
+                        vp.height as f32,
+                        0.0,
+                        1.0,
+                    );
+                    render_pass.set_scissor_rect(vp.x, vp.y, vp.width, vp.height);
+                    let bind_group = match &pane.nearest_bind_group {
+                        Some(nearest) if self.display.use_nearest(scale) => nearest,
+                        _ => pane.bind_group.as_ref().unwrap(),
+                    };
+                    render_pass.set_bind_group(0, bind_group, &[]);
+                    render_pass.set_bind_group(1, &pane.transform.bind_group, &[]);
+                    render_pass.set_pipeline(self.pipelines.get(grid_key));
+                    render_pass.draw_indexed(0..pane.quad.index_count(), 0, 0..1);
+                }
+
+                if let Some(rect) = self.minimap_rect(index) {
+                    render_pass.set_viewport(
+                        rect.x as f32,
+                        rect.y as f32,
+                        rect.width as f32,
+                        rect.height as f32,
+                        0.0,
+                        1.0,
+                    );
+                    render_pass.set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+                    render_pass.set_pipeline(self.pipelines.get(minimap_key));
+                    render_pass.set_bind_group(0, pane.bind_group.as_ref().unwrap(), &[]);
+                    render_pass.set_bind_group(1, &pane.minimap_transform.bind_group, &[]);
+                    render_pass.draw_indexed(0..pane.quad.index_count(), 0, 0..1);
+                }
+            }
+
+            // The loupe is drawn on top of everything in the pane under the cursor.
+            if let Some((index, rect, scale)) = loupe {
+                let pane = &self.panes[index];
+                let vp = &pane.viewport;
+                render_pass.set_viewport(
+                    vp.x as f32,
+                    vp.y as f32,
+                    vp.width as f32,
+                    vp.height as f32,
+                    0.0,
+                    1.0,
+                );
+                render_pass.set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+                let bind_group = match &pane.nearest_bind_group {
+                    Some(nearest) if self.display.use_nearest(scale) => nearest,
+                    _ => pane.bind_group.as_ref().unwrap(),
+                };
+                render_pass.set_pipeline(self.pipelines.get(loupe_key));
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.set_bind_group(1, &self.loupe_transform.bind_group, &[]);
+                render_pass.draw_indexed(0..self.loupe_quad.index_count(), 0, 0..1);
+            }
+        }
+
+        self.filters.present(&mut encoder, render_target.view());
+
         // if let Some(msg) = &self.status_message {
         //     // Text drawing
         //     let section = wgpu_glyph::Section {
@@ -446,21 +705,1045 @@ where
         //log::info!("Render");
     }
 
-    pub fn update_position(&mut self, pos: (f32, f32)) {
-        self.view.set_position((pos.0, pos.1));
+    /// Make the pane under the given canvas position (in logical pixels) the active pane.
+    pub fn activate_pane_at(&mut self, pos: (f32, f32)) -> Option<usize> {
+        self.activate_pane_at_physical(self.to_physical(pos))
+    }
+
+    fn activate_pane_at_physical(&mut self, pos: (f32, f32)) -> Option<usize> {
+        let index = self
+            .panes
+            .iter()
+            .position(|pane| pane.viewport.contains(pos))?;
+        if index != self.active_pane {
+            self.active_pane = index;
+            self.dirty = true;
+        }
+        Some(index)
+    }
+
+    pub fn active_pane(&self) -> usize {
+        self.active_pane
+    }
+
+    /// Select the pane that receives the current interaction, the first
+    /// position of a drag activates the pane under the cursor.
+    fn begin_interaction(&mut self, pos: (f32, f32)) -> usize {
+        if let Some(index) = self.interaction_pane {
+            return index;
+        }
+        let index = self
+            .activate_pane_at_physical(pos)
+            .unwrap_or(self.active_pane);
+        self.interaction_pane = Some(index);
+        // Grabbing the image stops any motion.
+        self.stop_motion();
+        self.pointer_velocity.clear();
+        // The whole drag becomes a single undo step.
+        self.history.begin_gesture(self.view_snapshot());
+        index
+    }
+
+    /// The panes affected by a view change in the pane with the given index.
+    fn linked_panes(&self, index: usize) -> Vec<usize> {
+        match self.view_link {
+            ViewLink::Independent => vec![index],
+            ViewLink::Linked | ViewLink::Shared => (0..self.panes.len()).collect(),
+        }
+    }
+
+    /// Pan the view to a position in logical pixels, `now` is the time of the
+    /// pointer event in milliseconds. Returns the resulting change of the view if any.
+    pub fn update_position(&mut self, pos: (f32, f32), now: f64) -> Option<ViewChange> {
+        let pos = self.to_physical(pos);
+        // A drag starting on a minimap pans the view to the point under the cursor.
+        if self.interaction_pane.is_none() {
+            if let Some(index) = self.minimap_at(pos) {
+                self.minimap.grab(index);
+            }
+        }
+        if let Some(index) = self.minimap.dragging() {
+            return self.drag_minimap(index, pos);
+        }
+        // A drag starting on the swipe divider moves the divider instead of the image.
+        if self.interaction_pane.is_none()
+            && self.compare.grab_divider(pos, self.scale_factor as f32)
+        {
+            self.interaction_pane = Some(0);
+        }
+        if self.compare.is_dragging_divider() {
+            self.compare.divider = pos.0.max(0.0).min(self.size.0 as f32);
+            self.dirty = true;
+            return None;
+        }
+
+        let index = self.begin_interaction(pos);
+        self.pointer_velocity.add(now, pos);
+        let before = self.panes[index].view.get_displacement();
+        // All linked panes get the same local position, so they move in lock-step.
+        let local = self.panes[index].viewport.to_local(pos);
+        for i in self.linked_panes(index) {
+            let pane = &mut self.panes[i];
+            let pane_before = pane.view.get_displacement();
+            pane.view.set_position(local);
+            pane.quad
+                .constrain(&mut pane.view, &self.constraints, pane_before, true);
+        }
         //log::info!("Update: {:?}", self.view);
 
         self.dirty = true;
+
+        let pane = &self.panes[index];
+        let after = pane.view.get_displacement();
+        let scale = pane.quad.image_scale(&pane.view);
+        let screen = (after.0 - before.0, after.1 - before.1);
+        Some(ViewChange::Pan {
+            screen,
+            image: (screen.0 / scale, screen.1 / scale),
+        })
+    }
+
+    /// Position the magnified view under the loupe. Returns the pane under the
+    /// loupe, the part of the canvas to draw it in and its scale.
+    fn update_loupe(&mut self) -> Option<(usize, Rect, f32)> {
+        let pos = self.loupe.position.filter(|_| self.loupe.enabled)?;
+        let index = self
+            .panes
+            .iter()
+            .position(|pane| pane.is_visible() && pane.viewport.contains(pos))?;
+        let pane = &self.panes[index];
+        let rect = self.loupe.rect(pane.viewport, self.scale_factor as f32)?;
+
+        // The image point under the cursor stays under the cursor in the magnified view.
+        let local = pane.viewport.to_local(pos);
+        let point = pane.quad.screen_to_image(&pane.view, local);
+        let scale = pane.quad.image_scale(&pane.view) * self.loupe.magnification;
+        let image_size = pane.quad.image_size();
+        let viewport_size = pane.viewport.size();
+        self.loupe_quad.set_viewport_size(viewport_size);
+        self.loupe_quad.map_texture_coords(image_size, image_size);
+        let mut view = ViewState::new();
+        let target = SavedView {
+            zoom: Zoom::Pixel(scale),
+            center: (point.0 / image_size.0, point.1 / image_size.1),
+        };
+        self.loupe_quad.restore_view(&target, &mut view);
+        view.pan_by((
+            local.0 - viewport_size.0 / 2.0,
+            local.1 - viewport_size.1 / 2.0,
+        ));
+
+        self.loupe_transform
+            .write(&self.queue, self.loupe_quad.transform_uniforms(&view));
+        self.queue.write_buffer(
+            &self.loupe_buffer,
+            0,
+            bytemuck::cast_slice(&[self.loupe.uniforms(self.scale_factor as f32)]),
+        );
+        Some((index, rect, scale))
+    }
+
+    /// The minimap of a pane in canvas coordinates, if it is shown.
+    fn minimap_rect(&self, index: usize) -> Option<Rect> {
+        let pane = &self.panes[index];
+        pane.image?;
+        self.minimap.rect(
+            pane.viewport,
+            pane.quad.image_size(),
+            self.scale_factor as f32,
+        )
+    }
+
+    fn minimap_at(&self, pos: (f32, f32)) -> Option<usize> {
+        (0..self.panes.len()).find(|&index| {
+            self.minimap_rect(index)
+                .map_or(false, |rect| rect.contains(pos))
+        })
+    }
+
+    /// Center the view of a pane on the image point under a position in its minimap.
+    fn drag_minimap(&mut self, index: usize, pos: (f32, f32)) -> Option<ViewChange> {
+        let rect = self.minimap_rect(index)?;
+        self.begin_interaction(pos);
+
+        let pane = &mut self.panes[index];
+        let image_size = pane.quad.image_size();
+        let point = Minimap::to_image(&rect, image_size, pos);
+        let before = pane.view.get_displacement();
+        let target = SavedView {
+            zoom: pane.view.zoom,
+            center: (point.0 / image_size.0, point.1 / image_size.1),
+        };
+        pane.quad.restore_view(&target, &mut pane.view);
+        pane.quad
+            .constrain(&mut pane.view, &self.constraints, before, false);
+        let after = pane.view.get_displacement();
+        let scale = pane.quad.image_scale(&pane.view);
+        let screen = (after.0 - before.0, after.1 - before.1);
+
+        // Linked panes move by the same amount.
+        for i in self.linked_panes(index) {
+            if i == index {
+                continue;
+            }
+            let pane = &mut self.panes[i];
+            let pane_before = pane.view.get_displacement();
+            pane.view.pan_by(screen);
+            pane.quad
+                .constrain(&mut pane.view, &self.constraints, pane_before, false);
+        }
+
+        self.dirty = true;
+        Some(ViewChange::Pan {
+            screen,
+            image: (screen.0 / scale, screen.1 / scale),
+        })
+    }
+
+    /// Zoom the view, returns the resulting change of the view.
+    pub fn update_zoom(&mut self, pos: (f32, f32)) -> Option<ViewChange> {
+        let index = self.begin_interaction(self.to_physical(pos));
+        let before = self.panes[index].view.magnification();
+        // The zoom speed is defined in logical pixels, so use the logical position.
+        let viewport = self.panes[index].viewport;
+        let scale = self.scale_factor as f32;
+        let local = (
+            pos.0 - viewport.x as f32 / scale,
+            pos.1 - viewport.y as f32 / scale,
+        );
+        for i in self.linked_panes(index) {
+            let pane = &mut self.panes[i];
+            let pane_before = pane.view.get_displacement();
+            pane.view.set_zoom(local);
+            pane.quad
+                .constrain(&mut pane.view, &self.constraints, pane_before, true);
+        }
+
+        self.dirty = true;
+        Some(ViewChange::Zoom(
+            self.panes[index].view.magnification() / before,
+        ))
+    }
+
+    /// Apply a change made in another view to the active pane.
+    pub fn apply_view_change(&mut self, change: ViewChange, space: SyncSpace) {
+        match change {
+            ViewChange::Pan { screen, image } => self.update_linked_views(|pane| {
+                let delta = match space {
+                    SyncSpace::Screen => screen,
+                    SyncSpace::Image => {
+                        let scale = pane.quad.image_scale(&pane.view);
+                        (image.0 * scale, image.1 * scale)
+                    }
+                };
+                pane.view.pan_by(delta);
+            }),
+            ViewChange::Zoom(factor) => {
+                self.update_linked_views(|pane| pane.view.update_magnification(factor))
+            }
+            ViewChange::Window { center, width } => self.set_window(center, width),
+            ViewChange::Frame(index) => {
+                // Sequences without that frame stay where they are.
+                if let Some(slot) = self.panes[self.active_pane].image {
+                    self.set_frame(slot, index).ok();
+                }
+            }
+        }
+    }
+
+    // Update the view of the active pane and the panes linked to it, within
+    // the constraints.
+    fn update_linked_views(&mut self, update: impl Fn(&mut Pane)) {
+        for i in self.linked_panes(self.active_pane) {
+            let pane = &mut self.panes[i];
+            update(pane);
+            let disp = pane.view.get_displacement();
+            pane.quad
+                .constrain(&mut pane.view, &self.constraints, disp, false);
+        }
+        self.dirty = true;
+    }
+
+    /// The properties of the active pane that synchronized views follow.
+    pub fn sync_snapshot(&self) -> ViewSnapshot {
+        let pane = &self.panes[self.active_pane];
+        ViewSnapshot {
+            position: pane.view.get_displacement(),
+            scale: pane.quad.image_scale(&pane.view),
+            window: self.window(),
+            frame: pane
+                .image
+                .and_then(|slot| self.playbacks.get(&slot))
+                .map(Playback::current),
+        }
+    }
+
+    /// End the current interaction, `now` is the time of the release in
+    /// milliseconds. A pan that was moving at release continues to glide.
+    pub fn clear_anchor(&mut self, now: f64) {
+        for pane in self.panes.iter_mut() {
+            pane.view.clear_anchor();
+        }
+        if let Some(index) = self.interaction_pane {
+            self.inertia = Inertia::start(self.pointer_velocity.velocity(now), now)
+                .map(|inertia| (index, inertia));
+        }
+        self.pointer_velocity.clear();
+        self.interaction_pane = None;
+        self.compare.release_divider();
+        self.minimap.release();
+        self.history.end_gesture();
+
+        // Spring back from any elastic overscroll.
+        for i in 0..self.panes.len() {
+            let pane = &self.panes[i];
+            let mut view = pane.view.clone();
+            let disp = view.get_displacement();
+            pane.quad
+                .constrain(&mut view, &self.constraints, disp, false);
+            if view.pos != pane.view.pos {
+                let target = pane.quad.save_view(&view);
+                self.transition_to(i, target, true);
+            }
+        }
+    }
+
+    pub fn set_constraints(&mut self, constraints: ViewConstraints) {
+        self.constraints = constraints;
+        self.constrain_all();
+    }
+
+    /// Enforce the constraints on all panes, without overscroll.
+    fn constrain_all(&mut self) {
+        for pane in self.panes.iter_mut() {
+            let disp = pane.view.get_displacement();
+            pane.quad
+                .constrain(&mut pane.view, &self.constraints, disp, false);
+        }
+        self.dirty = true;
+    }
+
+    /// Stop transitions and inertia.
+    fn stop_motion(&mut self) {
+        for pane in self.panes.iter_mut() {
+            pane.animation = None;
+        }
+        self.inertia = None;
+    }
+
+    fn view_snapshot(&self) -> Vec<ViewState> {
+        self.panes
+            .iter()
+            .map(|pane| {
+                let mut view = pane.view.clone();
+                view.clear_anchor();
+                view
+            })
+            .collect()
+    }
+
+    fn restore_views(&mut self, views: Vec<ViewState>) {
+        // Panes added after the snapshot was taken keep their view.
+        self.stop_motion();
+        for (pane, view) in self.panes.iter_mut().zip(views.into_iter()) {
+            pane.view = view;
+        }
+        self.constrain_all();
+        self.interaction_pane = None;
+        self.dirty = true;
+    }
+
+    /// Move the view of a pane to the target, either directly or with a transition.
+    fn transition_to(&mut self, index: usize, target: SavedView, animated: bool) {
+        self.inertia = None;
+        let pane = &mut self.panes[index];
+        if animated {
+            let from = pane.quad.save_view(&pane.view);
+            pane.animation = Some(ViewAnimation::new(
+                from,
+                target,
+                TRANSITION_DURATION,
+                Easing::EaseInOut,
+            ));
+        } else {
+            pane.animation = None;
+            pane.quad.restore_view(&target, &mut pane.view);
+        }
+        self.dirty = true;
+    }
+
+    /// Advance the running transitions, inertia and frame playback, returns true
+    /// while any of them is running.
+    pub fn animate(&mut self, now: f64) -> bool {
+        let mut running = false;
+        if let Some((index, inertia)) = self.inertia.as_mut() {
+            let index = *index;
+            let delta = inertia.step(now);
+            let mut finished = inertia.is_finished();
+            for i in self.linked_panes(index) {
+                let pane = &mut self.panes[i];
+                let wanted = pane.view.get_displacement();
+                let wanted = (wanted.0 + delta.0, wanted.1 + delta.1);
+                pane.view.pan_by(delta);
+                pane.quad
+                    .constrain(&mut pane.view, &self.constraints, wanted, false);
+                // Stop gliding when hitting the limits.
+                finished |= pane.view.get_displacement() != wanted;
+            }
+            if finished {
+                self.inertia = None;
+            } else {
+                running = true;
+            }
+            self.dirty = true;
+        }
+
+        for pane in self.panes.iter_mut() {
+            if let Some(animation) = pane.animation.as_mut() {
+                let (view, finished) = animation.step(&pane.quad, now);
+                pane.quad.restore_view(&view, &mut pane.view);
+                let disp = pane.view.get_displacement();
+                pane.quad
+                    .constrain(&mut pane.view, &self.constraints, disp, false);
+                if finished {
+                    pane.animation = None;
+                } else {
+                    running = true;
+                }
+                self.dirty = true;
+            }
+        }
+        running |= self.advance_playbacks(now);
+        running
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.inertia.is_some()
+            || self.panes.iter().any(|pane| pane.animation.is_some())
+            || self.playbacks.values().any(Playback::is_playing)
+    }
+
+    /// Reset the view of the active pane, and the panes linked to it.
+    pub fn reset_view(&mut self, animated: bool) {
+        self.history.record(self.view_snapshot());
+        let target = SavedView {
+            zoom: Zoom::Fit(1.0),
+            center: (0.5, 0.5),
+        };
+        for i in self.linked_panes(self.active_pane) {
+            self.transition_to(i, target, animated);
+        }
+    }
+
+    /// Zoom so that the rectangle (x, y, width, height) in image pixels fills the viewport.
+    pub fn zoom_to_rect(&mut self, rect: (f32, f32, f32, f32), animated: bool) {
+        self.history.record(self.view_snapshot());
+        for i in self.linked_panes(self.active_pane) {
+            let pane = &self.panes[i];
+            let viewport_size = pane.quad.viewport_size();
+            let image_size = pane.quad.image_size();
+            let scale = (viewport_size.0 / rect.2.max(1.0)).min(viewport_size.1 / rect.3.max(1.0));
+            let target = SavedView {
+                zoom: pane.quad.zoom_with_scale(scale, pane.view.zoom),
+                center: (
+                    (rect.0 + rect.2 / 2.0) / image_size.0,
+                    (rect.1 + rect.3 / 2.0) / image_size.1,
+                ),
+            };
+            self.transition_to(i, target, animated);
+        }
+    }
+
+    /// Switch the zoom mode of the active pane, and the panes linked to it,
+    /// keeping the same point of the image in the center of the viewport.
+    pub fn set_zoom_mode(&mut self, zoom: Zoom, animated: bool) {
+        self.history.record(self.view_snapshot());
+        for i in self.linked_panes(self.active_pane) {
+            let pane = &self.panes[i];
+            let target = SavedView {
+                zoom,
+                ..pane.quad.save_view(&pane.view)
+            };
+            self.transition_to(i, target, animated);
+        }
+    }
+
+    pub fn zoom_mode(&self) -> Zoom {
+        self.panes[self.active_pane].view.zoom
+    }
+
+    /// Pan so that the point in image pixels is in the center of the viewport.
+    pub fn pan_to_point(&mut self, point: (f32, f32), animated: bool) {
+        self.history.record(self.view_snapshot());
+        for i in self.linked_panes(self.active_pane) {
+            let pane = &self.panes[i];
+            let image_size = pane.quad.image_size();
+            let target = SavedView {
+                zoom: pane.view.zoom,
+                center: (point.0 / image_size.0, point.1 / image_size.1),
+            };
+            self.transition_to(i, target, animated);
+        }
+    }
+
+    /// The views of all panes, in a form that survives a resize.
+    pub fn saved_views(&self) -> Vec<SavedView> {
+        self.panes
+            .iter()
+            .map(|pane| pane.quad.save_view(&pane.view))
+            .collect()
+    }
+
+    pub fn restore_saved_views(&mut self, views: &[SavedView]) {
+        self.history.record(self.view_snapshot());
+        self.stop_motion();
+        for (pane, saved) in self.panes.iter_mut().zip(views.iter()) {
+            pane.quad.restore_view(saved, &mut pane.view);
+            pane.view.clear_anchor();
+        }
+        self.constrain_all();
+        self.interaction_pane = None;
+        self.dirty = true;
     }
 
-    pub fn update_zoom(&mut self, pos: (f32, f32)) {
-        self.view.set_zoom((pos.0, pos.1));
+    pub fn undo(&mut self) {
+        if let Some(views) = self.history.undo(self.view_snapshot()) {
+            self.restore_views(views);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(views) = self.history.redo(self.view_snapshot()) {
+            self.restore_views(views);
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        let viewports = layout.viewports(self.size);
+        self.panes.truncate(viewports.len());
+        while self.panes.len() < viewports.len() {
+            let index = self.panes.len();
+            let mut pane = Pane::new(
+                &self.device,
+                &self.transform_bind_group_layout,
+                viewports[index],
+            );
+            if self.view_link == ViewLink::Shared {
+                pane.view = self.panes[0].view.clone();
+            }
+            self.panes.push(pane);
+            // Show the image with the same index as the pane, if there is one.
+            let slot = if self.has_image(index) { index } else { 0 };
+            self.set_pane_image(index, slot);
+        }
+        self.layout = layout;
+        self.active_pane = self.active_pane.min(self.panes.len() - 1);
+        self.interaction_pane = None;
+        self.update_viewports();
 
         self.dirty = true;
     }
 
-    pub fn clear_anchor(&mut self) {
-        self.view.clear_anchor();
+    pub fn set_view_link(&mut self, view_link: ViewLink) {
+        if view_link == ViewLink::Shared {
+            let view = self.panes[0].view.clone();
+            for pane in self.panes.iter_mut().skip(1) {
+                pane.view = view.clone();
+            }
+            self.dirty = true;
+        }
+        self.view_link = view_link;
+    }
+
+    fn has_image(&self, slot: usize) -> bool {
+        self.images.get(slot).map_or(false, Option::is_some)
+    }
+
+    /// Decode an encoded image (e.g. PNG, OpenEXR or Radiance HDR) and upload
+    /// it to the given image slot. TIFF and DICOM files are loaded as frames,
+    /// one per page or frame.
+    pub fn load_image(&mut self, slot: usize, image_bytes: &[u8]) -> Result<(), String> {
+        if tiff_frames::is_tiff(image_bytes) {
+            let frames = TiffFrames::new(image_bytes.to_vec())?;
+            return self.load_frames(slot, Box::new(frames)).map(|_| ());
+        }
+        if dicom::is_dicom(image_bytes) {
+            let frames = DicomFrames::new(image_bytes.to_vec())?;
+            return self.load_frames(slot, Box::new(frames)).map(|_| ());
+        }
+        self.playbacks.remove(&slot);
+        let texture = if hdr::is_hdr(image_bytes) {
+            let image = hdr::decode(image_bytes)?;
+            ImageTexture::from_float(&self.device, &self.queue, image)
+        } else {
+            let mut image = image::load_from_memory(image_bytes)
+                .map_err(|e| e.to_string())?
+                .into_rgba();
+            if let Some(transfer) = color::png_transfer(image_bytes) {
+                color::convert_to_srgb(&mut image, &transfer);
+            }
+            ImageTexture::from_rgba(&self.device, &self.queue, image)
+        };
+        self.set_image(slot, texture);
+        Ok(())
+    }
+
+    /// Upload a headerless buffer of `width` x `height` pixels to an image slot,
+    /// converted on the GPU. `stride` is the number of bytes from one row to
+    /// the next, 0 if the rows are packed. The byte order only matters for
+    /// 16-bit formats, which keep their raw values like 16-bit TIFF pages.
+    #[allow(clippy::too_many_arguments)]
+    pub fn load_raw(
+        &mut self,
+        slot: usize,
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        stride: u32,
+        endianness: Endianness,
+    ) -> Result<(), String> {
+        let image = RawImage::new(bytes.to_vec(), width, height, format, stride, endianness)?;
+        self.playbacks.remove(&slot);
+        let texture = self.raw_converter.convert(&self.device, &self.queue, image);
+        self.set_image(slot, texture);
+        Ok(())
+    }
+
+    /// Show the frames of a source in an image slot, starting with the first.
+    /// Returns the number of frames.
+    pub fn load_frames(
+        &mut self,
+        slot: usize,
+        source: Box<dyn FrameSource>,
+    ) -> Result<usize, String> {
+        let mut playback = Playback::new(source);
+        if playback.frame_count() == 0 {
+            return Err("No frames to show".to_string());
+        }
+        let frame = playback.frame()?;
+        let count = playback.frame_count();
+        self.set_frame_image(slot, frame, false);
+        self.playbacks.insert(slot, playback);
+        Ok(count)
+    }
+
+    // Upload a frame, keeping the window of the previous frame if asked to.
+    fn set_frame_image(&mut self, slot: usize, frame: Frame, keep_window: bool) {
+        let previous = self
+            .images
+            .get(slot)
+            .and_then(Option::as_ref)
+            .and_then(ImageTexture::window);
+        let keep = |window: Option<WindowLevel>| match previous {
+            Some(_) if keep_window && window.is_some() => previous,
+            _ => window,
+        };
+        // Frames of the same size and format are written into the texture
+        // shown, keeping its bind groups.
+        let frame = match self.images.get_mut(slot).and_then(Option::as_mut) {
+            Some(texture) => match texture.write_frame(&self.queue, frame) {
+                Ok(()) => {
+                    texture.set_window(&self.queue, keep(texture.default_window));
+                    self.dirty = true;
+                    return;
+                }
+                Err(frame) => frame,
+            },
+            None => frame,
+        };
+        let mut texture = match (frame.pixels, frame.window) {
+            (Pixels::Rgba8(image), _) => ImageTexture::from_rgba(&self.device, &self.queue, image),
+            (Pixels::Float(image), Some(window)) => {
+                ImageTexture::from_raw(&self.device, &self.queue, image, window)
+            }
+            (Pixels::Float(image), None) => {
+                ImageTexture::from_float(&self.device, &self.queue, image)
+            }
+            (Pixels::Raw(image), _) => self.raw_converter.convert(&self.device, &self.queue, image),
+        };
+        let window = keep(texture.window());
+        texture.set_window(&self.queue, window);
+        texture.pixel_spacing = frame.pixel_spacing;
+        self.set_image(slot, texture);
+    }
+
+    /// The number of frames in an image slot, 1 for still images and 0 for
+    /// empty slots.
+    pub fn frame_count(&self, slot: usize) -> usize {
+        match self.playbacks.get(&slot) {
+            Some(playback) => playback.frame_count(),
+            None => self.has_image(slot) as usize,
+        }
+    }
+
+    /// The index of the frame shown in an image slot.
+    pub fn current_frame(&self, slot: usize) -> usize {
+        self.playbacks.get(&slot).map_or(0, Playback::current)
+    }
+
+    /// Show a frame of the sequence in an image slot. The window set for the
+    /// previous frame is kept.
+    pub fn set_frame(&mut self, slot: usize, index: usize) -> Result<(), String> {
+        let playback = self
+            .playbacks
+            .get_mut(&slot)
+            .ok_or_else(|| format!("No frames in image slot {}", slot))?;
+        if !playback.seek(index) {
+            return Err(format!("No frame {} in image slot {}", index, slot));
+        }
+        let frame = playback.frame()?;
+        self.set_frame_image(slot, frame, true);
+        Ok(())
+    }
+
+    /// Play the frames of an image slot at `fps` frames per second, looping or
+    /// stopping at the last frame.
+    pub fn play(&mut self, slot: usize, fps: f64, looping: bool, now: f64) {
+        if let Some(playback) = self.playbacks.get_mut(&slot) {
+            playback.looping = looping;
+            playback.play(fps, now);
+        }
+    }
+
+    pub fn pause(&mut self, slot: usize) {
+        if let Some(playback) = self.playbacks.get_mut(&slot) {
+            playback.pause();
+        }
+    }
+
+    pub fn is_playing(&self, slot: usize) -> bool {
+        self.playbacks
+            .get(&slot)
+            .map_or(false, Playback::is_playing)
+    }
+
+    // Show the frames that are due, returns true while any sequence is playing.
+    fn advance_playbacks(&mut self, now: f64) -> bool {
+        let mut due = Vec::new();
+        for (&slot, playback) in self.playbacks.iter_mut() {
+            if playback.advance(now).is_some() {
+                due.push((slot, playback.frame()));
+            }
+        }
+        for (slot, frame) in due {
+            match frame {
+                Ok(frame) => self.set_frame_image(slot, frame, true),
+                Err(e) => {
+                    log::error!("{}", e);
+                    self.pause(slot);
+                }
+            }
+        }
+        self.playbacks.values().any(Playback::is_playing)
+    }
+
+    /// The window of the image in the active pane, None if it is shown as is.
+    pub fn window(&self) -> Option<WindowLevel> {
+        let slot = self.panes[self.active_pane].image?;
+        self.images.get(slot)?.as_ref()?.window()
+    }
+
+    /// Set the window of the image in the active pane, in raw values. Only
+    /// raw images, e.g. 16-bit TIFF pages or DICOM, have a window. A negative
+    /// width inverts.
+    pub fn set_window(&mut self, center: f32, width: f32) {
+        let window = WindowLevel { center, width };
+        self.update_window(|_| Some(window));
+    }
+
+    /// Go back to the window the image in the active pane was loaded with.
+    pub fn reset_window(&mut self) {
+        self.update_window(|texture| texture.default_window);
+    }
+
+    fn update_window(&mut self, window: impl Fn(&ImageTexture) -> Option<WindowLevel>) {
+        let slot = match self.panes[self.active_pane].image {
+            Some(slot) => slot,
+            None => return,
+        };
+        if let Some(texture) = self.images.get_mut(slot).and_then(Option::as_mut) {
+            if texture.window().is_some() {
+                let window = window(texture);
+                texture.set_window(&self.queue, window);
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn set_image(&mut self, slot: usize, texture: ImageTexture) {
+        if self.images.len() <= slot {
+            self.images.resize_with(slot + 1, || None);
+        }
+        self.images[slot] = Some(texture);
+
+        // Refresh the bind groups since the slot may be shown or compared against,
+        // and give panes without an image something to show.
+        for index in 0..self.panes.len() {
+            let current = self.panes[index].image;
+            match current {
+                Some(_) => self.refresh_pane(index),
+                None => self.set_pane_image(index, slot),
+            }
+        }
+    }
+
+    // The visible pane at a position in physical pixels and its image.
+    fn pane_image_at(&self, pos: (f32, f32)) -> Option<(&Pane, &ImageTexture)> {
+        let pane = self
+            .panes
+            .iter()
+            .find(|pane| pane.is_visible() && pane.viewport.contains(pos))?;
+        let texture = self.images.get(pane.image?)?.as_ref()?;
+        Some((pane, texture))
+    }
+
+    /// The image pixel under a canvas position in logical pixels and its value,
+    /// see `ImageTexture::probe`.
+    pub fn probe(&self, pos: (f32, f32)) -> Option<((u32, u32), [f32; 4])> {
+        let pos = self.to_physical(pos);
+        let (pane, texture) = self.pane_image_at(pos)?;
+        let point = pane
+            .quad
+            .screen_to_image(&pane.view, pane.viewport.to_local(pos));
+        if point.0 < 0.0 || point.1 < 0.0 {
+            return None;
+        }
+        let pixel = (point.0 as u32, point.1 as u32);
+        texture.probe(pixel.0, pixel.1).map(|value| (pixel, value))
+    }
+
+    /// The distance between two canvas positions in logical pixels, in the
+    /// image of the pane under `from`. Returned in image pixels, and in
+    /// millimeters if the pixel spacing of the image is known.
+    pub fn measure(&self, from: (f32, f32), to: (f32, f32)) -> Option<(f32, Option<f32>)> {
+        let from = self.to_physical(from);
+        let to = self.to_physical(to);
+        let (pane, texture) = self.pane_image_at(from)?;
+        let a = pane
+            .quad
+            .screen_to_image(&pane.view, pane.viewport.to_local(from));
+        let b = pane
+            .quad
+            .screen_to_image(&pane.view, pane.viewport.to_local(to));
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let millimeters = texture
+            .pixel_spacing
+            .map(|(sx, sy)| ((dx * sx).powi(2) + (dy * sy).powi(2)).sqrt());
+        Some(((dx * dx + dy * dy).sqrt(), millimeters))
+    }
+
+    /// Width and height of the pixels of the image in the active pane in
+    /// millimeters, e.g. from DICOM.
+    pub fn pixel_spacing(&self) -> Option<(f32, f32)> {
+        let slot = self.panes[self.active_pane].image?;
+        self.images.get(slot)?.as_ref()?.pixel_spacing
+    }
+
+    pub fn set_pane_image(&mut self, pane: usize, slot: usize) {
+        if pane >= self.panes.len() || !self.has_image(slot) {
+            return;
+        }
+        let size = self.images[slot].as_ref().unwrap().size_f32();
+        self.panes[pane].set_image_size(size);
+        self.panes[pane].image = Some(slot);
+        self.refresh_pane(pane);
+    }
+
+    /// Rebuild the bind group of a pane from its image and the compare image.
+    fn refresh_pane(&mut self, index: usize) {
+        let slot = match self.panes[index].image {
+            Some(slot) => slot,
+            None => return,
+        };
+        let texture = self.images[slot].as_ref().unwrap();
+        // Compare the image with itself if there is nothing to compare against.
+        let compare_texture = self
+            .images
+            .get(self.compare.image)
+            .and_then(Option::as_ref)
+            .unwrap_or(texture);
+        let minimap_buffer = &self.panes[index].minimap_buffer;
+        // Textures that can't be filtered are always sampled with nearest.
+        let sampler = if texture.filterable && compare_texture.filterable {
+            &self.texture_sampler
+        } else {
+            &self.nearest_sampler
+        };
+        let bind_group = self.create_bind_group(
+            &texture.view,
+            &compare_texture.view,
+            sampler,
+            minimap_buffer,
+            &texture.uniform_buffer,
+            &compare_texture.uniform_buffer,
+        );
+        let nearest_bind_group = self.create_bind_group(
+            &texture.view,
+            &compare_texture.view,
+            &self.nearest_sampler,
+            minimap_buffer,
+            &texture.uniform_buffer,
+            &compare_texture.uniform_buffer,
+        );
+        self.panes[index].bind_group = Some(bind_group);
+        self.panes[index].nearest_bind_group = Some(nearest_bind_group);
+        self.dirty = true;
+    }
+
+    pub fn set_compare_mode(&mut self, mode: Option<CompareMode>) {
+        // Put the divider in the middle if it is not on screen.
+        if self.compare.divider <= 0.0 || self.compare.divider >= self.size.0 as f32 {
+            self.compare.divider = self.size.0 as f32 / 2.0;
+        }
+        self.compare.mode = mode;
+        self.dirty = true;
+    }
+
+    pub fn compare_mode(&self) -> Option<CompareMode> {
+        self.compare.mode
+    }
+
+    /// Select the image slot that the panes are compared against.
+    pub fn set_compare_image(&mut self, slot: usize) {
+        self.compare.image = slot;
+        for index in 0..self.panes.len() {
+            self.refresh_pane(index);
+        }
+    }
+
+    pub fn set_compare_params(&mut self, gain: f32, opacity: f32, checker_size: f32) {
+        self.compare.gain = gain;
+        self.compare.opacity = opacity.max(0.0).min(1.0);
+        self.compare.checker_size = checker_size;
+        self.dirty = true;
+    }
+
+    pub fn display(&self) -> &DisplaySettings {
+        &self.display
+    }
+
+    /// Show the pixel grid and the pixel values when zoomed in far enough.
+    pub fn set_pixel_grid(&mut self, show_grid: bool, show_values: bool) {
+        self.display.show_grid = show_grid;
+        self.display.show_values = show_values;
+        self.dirty = true;
+    }
+
+    /// Append a post-processing filter written in WGSL, returns its index in
+    /// the chain or the compile error.
+    pub fn add_filter(&mut self, source: &str, params: &[f32]) -> Result<usize, String> {
+        let index = self
+            .filters
+            .add(&self.device, &self.queue, source, params)?;
+        self.dirty = true;
+        Ok(index)
+    }
+
+    /// Append one of the built-in filters, see `BuiltinFilter` for the parameters.
+    pub fn add_builtin_filter(&mut self, filter: BuiltinFilter, params: &[f32]) -> usize {
+        self.dirty = true;
+        self.filters
+            .add_builtin(&self.device, &self.queue, filter, params)
+    }
+
+    /// Change the parameters of a filter, this doesn't rebuild its passes.
+    pub fn set_filter_params(&mut self, index: usize, params: &[f32]) -> bool {
+        self.dirty = true;
+        self.filters.set_params(&self.queue, index, params)
+    }
+
+    pub fn remove_filter(&mut self, index: usize) -> bool {
+        self.dirty = true;
+        self.filters.remove(index)
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+        self.dirty = true;
+    }
+
+    /// Scale the linear scene by 2^`stops` before it is displayed.
+    pub fn set_exposure(&mut self, stops: f32) {
+        self.display_transform.exposure = stops;
+        self.filters
+            .set_display_transform(&self.queue, &self.display_transform);
+        self.dirty = true;
+    }
+
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        self.display_transform.tone_map = tone_map;
+        self.filters
+            .set_display_transform(&self.queue, &self.display_transform);
+        self.dirty = true;
+    }
+
+    /// Show a circular loupe of `radius` logical pixels around the cursor,
+    /// magnifying the view under it by `magnification`.
+    pub fn set_loupe(&mut self, enabled: bool, radius: f32, magnification: f32) {
+        self.loupe.enabled = enabled;
+        self.loupe.radius = radius.max(1.0);
+        self.loupe.magnification = magnification.max(1.0);
+        self.dirty = true;
+    }
+
+    pub fn loupe_enabled(&self) -> bool {
+        self.loupe.enabled
+    }
+
+    /// Move the loupe to a canvas position in logical pixels, None hides it.
+    pub fn set_loupe_position(&mut self, pos: Option<(f32, f32)>) {
+        self.loupe.position = pos.map(|pos| self.to_physical(pos));
+        if self.loupe.enabled {
+            self.dirty = true;
+        }
+    }
+
+    /// Show a thumbnail of the image with the visible region in each pane,
+    /// `size` is the longer side of the thumbnail in logical pixels.
+    pub fn set_minimap(&mut self, enabled: bool, size: f32) {
+        self.minimap.enabled = enabled;
+        self.minimap.size = size;
+        self.dirty = true;
+    }
+
+    pub fn minimap_enabled(&self) -> bool {
+        self.minimap.enabled
+    }
+
+    /// Show one channel of the image as gray, or all of them.
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.display.channel = channel;
+        self.dirty = true;
+    }
+
+    /// How transparent pixels are shown, `checker_size` is the size of the
+    /// checkerboard squares in logical pixels.
+    pub fn set_alpha_mode(&mut self, alpha: AlphaMode, checker_size: f32) {
+        self.display.alpha = alpha;
+        self.display.checker_size = checker_size;
+        self.dirty = true;
+    }
+
+    /// The sRGB color of the canvas around the images and of the `Background`
+    /// alpha mode.
+    pub fn set_background(&mut self, color: [f32; 3]) {
+        self.display.background = color;
+        self.display_transform.background = self.display.background_color();
+        self.filters
+            .set_display_transform(&self.queue, &self.display_transform);
+        self.dirty = true;
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.display.interpolation = interpolation;
+        self.dirty = true;
+    }
+
+    /// Sample with nearest neighbor from `scale` screen pixels per image pixel.
+    pub fn set_nearest_threshold(&mut self, scale: f32) {
+        self.display.nearest_min_scale = scale;
+        self.dirty = true;
     }
 
     pub fn swap_image(&mut self) {
@@ -471,38 +1754,17 @@ where
         // log::info!("Attempting to load an image....");
         // let new_image = image::open(Path::new(&fname)).unwrap().into_rgba();
         let image_bytes = include_bytes!(r"e:\temp\video_frames\0.png");
-        let new_image = image::load_from_memory(image_bytes).unwrap().into_rgba();
+        self.load_image(0, image_bytes).unwrap();
 
         //log::info!("Open took: {} ms", measure.elapsed().as_millis());
-        let image_dims = new_image.dimensions();
-
-
-        // Queue the copy of the texture data
-        self.queue.write_texture(
-            wgpu::TextureCopyView {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &new_image,
-            wgpu::TextureDataLayout {
-                offset: 0,
-                bytes_per_row: 4 * image_dims.0,
-                rows_per_image: image_dims.1,
-            },
-            wgpu::Extent3d {
-                width: image_dims.0,
-                height: image_dims.1,
-                depth: 1,
-            },
+    }
 
-        );
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
 
-        self.quad.map_texture_coords(
-            (image_dims.0 as f32, image_dims.1 as f32),
-            (1024_f32, 1024_f32),
-        );
-        self.dirty = true;
+    pub fn view_link(&self) -> ViewLink {
+        self.view_link
     }
 
     pub fn is_dirty(&self) -> bool {
@@ -511,8 +1773,38 @@ where
 }
 
 impl State<TextureTarget> {
+    /// The RGBA pixels of the last render, row by row.
     pub async fn get_render_target_data(&self) -> Vec<u8> {
-        //self.target.get_buffer(&self.device).await
-        Vec::new()
+        self.target.get_buffer(&self.device).await
+    }
+
+    /// An offscreen state for the GPU tests, None without a GPU adapter.
+    #[cfg(test)]
+    pub fn headless(size: (u32, u32)) -> Option<Self> {
+        let instance = wgpu::Instance::new();
+        let adapter = futures::executor::block_on(instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::Default,
+                compatible_surface: None,
+            },
+            wgpu::BackendBit::PRIMARY,
+        ));
+        if adapter.is_none() {
+            log::warn!("No GPU adapter, skipping");
+            return None;
+        }
+        Some(futures::executor::block_on(State::new(
+            instance,
+            size,
+            1.0,
+            TextureTarget::new(),
+        )))
+    }
+
+    /// Render and read back the pixels, for the GPU tests.
+    #[cfg(test)]
+    pub fn render_pixels(&mut self) -> Vec<u8> {
+        self.render();
+        futures::executor::block_on(self.get_render_target_data())
     }
 }
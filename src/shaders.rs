@@ -0,0 +1,317 @@
+use crate::vertex::Vertex;
+use std::collections::HashMap;
+
+// The SPIR-V that build.rs compiled from shaders/<name>.
+macro_rules! built_in {
+    ($name:literal) => {
+        &include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv"))[..]
+    };
+}
+
+/// Shader code to create a module from.
+pub enum ShaderSource<'a> {
+    /// SPIR-V binary, e.g. compiled from GLSL at build time.
+    SpirV(&'a [u8]),
+    /// WGSL text, compiled at runtime.
+    Wgsl(&'a str),
+}
+
+/// Parse and validate WGSL, errors are returned as text for the user.
+pub fn parse_wgsl(source: &str) -> Result<naga::Module, String> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| format!("Failed to parse shader: {:?}", e))?;
+    // The SPIR-V writer recurses forever on array types.
+    let has_array = module
+        .types
+        .iter()
+        .any(|(_, ty)| matches!(ty.inner, naga::TypeInner::Array { .. }));
+    if has_array {
+        return Err("Arrays are not supported in WGSL shaders".to_string());
+    }
+    naga::proc::Validator::new()
+        .validate(&module)
+        .map_err(|e| format!("Invalid shader: {:?}", e))?;
+    Ok(module)
+}
+
+pub fn write_spirv(module: &naga::Module) -> Vec<u32> {
+    let mut writer =
+        naga::back::spv::Writer::new(&module.header, naga::back::spv::WriterFlags::NONE);
+    writer.write(module)
+}
+
+/// Compile WGSL to SPIR-V, errors are returned as text for the user.
+pub fn compile_wgsl(source: &str) -> Result<Vec<u32>, String> {
+    parse_wgsl(source).map(|module| write_spirv(&module))
+}
+
+pub fn create_shader_module(
+    device: &wgpu::Device,
+    source: ShaderSource,
+) -> Result<wgpu::ShaderModule, String> {
+    let words = match source {
+        ShaderSource::SpirV(bytes) => wgpu::read_spirv(std::io::Cursor::new(bytes))
+            .map_err(|e| format!("Invalid SPIR-V: {:?}", e))?,
+        ShaderSource::Wgsl(text) => compile_wgsl(text)?,
+    };
+    Ok(device.create_shader_module(&words))
+}
+
+/// Load a shader from disk while developing, `.wgsl` files are compiled and
+/// anything else is read as SPIR-V.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn create_shader_from_file(
+    device: &wgpu::Device,
+    filename: &std::path::Path,
+) -> Result<wgpu::ShaderModule, String> {
+    let buffer = std::fs::read(filename)
+        .map_err(|e| format!("Failed to read {}: {}", filename.display(), e))?;
+    match filename.extension().and_then(|ext| ext.to_str()) {
+        Some("wgsl") => {
+            let text = String::from_utf8(buffer).map_err(|e| e.to_string())?;
+            create_shader_module(device, ShaderSource::Wgsl(&text))
+        }
+        _ => create_shader_module(device, ShaderSource::SpirV(&buffer)),
+    }
+}
+
+/// The vertex shader of passes that cover the whole render target.
+pub fn fullscreen_vertex_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    create_shader_module(device, ShaderSource::SpirV(built_in!("fullscreen.vert")))
+        .expect("Invalid vertex shader")
+}
+
+/// The fragment shader of the pass from the linear scene to the render target.
+pub fn output_fragment_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    create_shader_module(device, ShaderSource::SpirV(built_in!("output.frag")))
+        .expect("Invalid output shader")
+}
+
+/// The fragment shader converting raw buffers to image textures.
+pub fn raw_fragment_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    create_shader_module(device, ShaderSource::SpirV(built_in!("raw.frag")))
+        .expect("Invalid raw conversion shader")
+}
+
+/// The fragment shaders of the image pipelines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FragmentShader {
+    /// The image with the selected interpolation.
+    Image,
+    /// Two images compared.
+    Compare,
+    /// Pixel grid and values on top of the image.
+    Grid,
+    Minimap,
+    Loupe,
+}
+
+impl FragmentShader {
+    fn source(&self) -> ShaderSource<'static> {
+        ShaderSource::SpirV(match self {
+            FragmentShader::Image => built_in!("image.frag"),
+            FragmentShader::Compare => built_in!("compare.frag"),
+            FragmentShader::Grid => built_in!("grid.frag"),
+            FragmentShader::Minimap => built_in!("minimap.frag"),
+            FragmentShader::Loupe => built_in!("loupe.frag"),
+        })
+    }
+}
+
+/// The fragment shaders of the built-in filter passes, see `convolution`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterShader {
+    /// One direction of a separable Gaussian blur.
+    Gaussian,
+    UnsharpMask,
+    Sobel,
+    Median,
+    /// Erosion or dilation.
+    Morphology,
+}
+
+impl FilterShader {
+    pub fn source(&self) -> ShaderSource<'static> {
+        ShaderSource::SpirV(match self {
+            FilterShader::Gaussian => built_in!("gaussian.frag"),
+            FilterShader::UnsharpMask => built_in!("unsharp.frag"),
+            FilterShader::Sobel => built_in!("sobel.frag"),
+            FilterShader::Median => built_in!("median.frag"),
+            FilterShader::Morphology => built_in!("morphology.frag"),
+        })
+    }
+}
+
+/// How the output is combined with what is already drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Blend {
+    Replace,
+    /// Blend with the alpha of the output.
+    Alpha,
+}
+
+impl Blend {
+    fn color_descriptor(&self) -> wgpu::BlendDescriptor {
+        match self {
+            Blend::Replace => wgpu::BlendDescriptor::REPLACE,
+            Blend::Alpha => wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+        }
+    }
+
+    // The scene is composited over the background by its alpha, which the
+    // blended output covers like its color.
+    fn alpha_descriptor(&self) -> wgpu::BlendDescriptor {
+        match self {
+            Blend::Replace => wgpu::BlendDescriptor::REPLACE,
+            Blend::Alpha => wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+        }
+    }
+}
+
+/// Identifies a variant of the image pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub fragment: FragmentShader,
+    pub blend: Blend,
+}
+
+impl PipelineKey {
+    pub fn new(fragment: FragmentShader, blend: Blend) -> Self {
+        PipelineKey { fragment, blend }
+    }
+}
+
+/// Render pipelines that draw the unit quad, built on first use and kept
+/// for the lifetime of the render target.
+pub struct PipelineCache {
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    vertex: wgpu::ShaderModule,
+    fragments: HashMap<FragmentShader, wgpu::ShaderModule>,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+}
+
+impl PipelineCache {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        let layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { bind_group_layouts });
+        let vertex = create_shader_module(device, ShaderSource::SpirV(built_in!("image.vert")))
+            .expect("Invalid vertex shader");
+        PipelineCache {
+            format,
+            layout,
+            vertex,
+            fragments: HashMap::new(),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Build the pipeline for the key unless it is already cached.
+    pub fn prepare(&mut self, device: &wgpu::Device, key: PipelineKey) {
+        if self.pipelines.contains_key(&key) {
+            return;
+        }
+        let fragment = self.fragments.entry(key.fragment).or_insert_with(|| {
+            create_shader_module(device, key.fragment.source()).expect("Invalid built-in shader")
+        });
+        let pipeline = build_render_pipeline(
+            device,
+            self.format,
+            &self.layout,
+            &self.vertex,
+            fragment,
+            &key.blend.color_descriptor(),
+            &key.blend.alpha_descriptor(),
+        );
+        log::info!("Pipeline created: {:?}", key);
+        self.pipelines.insert(key, pipeline);
+    }
+
+    /// A pipeline built by `prepare`.
+    pub fn get(&self, key: PipelineKey) -> &wgpu::RenderPipeline {
+        self.pipelines
+            .get(&key)
+            .unwrap_or_else(|| panic!("Pipeline not prepared: {:?}", key))
+    }
+}
+
+pub fn build_render_pipeline(
+    device: &wgpu::Device,
+    swap_texture_format: wgpu::TextureFormat,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    color_blend: &wgpu::BlendDescriptor,
+    alpha_blend: &wgpu::BlendDescriptor,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        layout,
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            depth_bias: 0,
+            depth_bias_clamp: 0.0,
+            depth_bias_slope_scale: 0.0,
+        }),
+        color_states: &[wgpu::ColorStateDescriptor {
+            format: swap_texture_format,
+            color_blend: color_blend.clone(),
+            alpha_blend: alpha_blend.clone(),
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint16,
+            vertex_buffers: &[Vertex::to_desc()],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wgsl_errors_are_returned() {
+        let source = "
+[[location 0]] var<out> f_color : vec4<f32>;
+fn main() -> void {
+  f_color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+  return;
+}
+entry_point fragment as \"main\" = main;
+";
+        assert!(compile_wgsl(source).is_ok());
+        // Missing semicolons.
+        assert!(compile_wgsl(&source.replace(';', "")).is_err());
+        // Undeclared variable.
+        assert!(compile_wgsl(&source.replace("f_color =", "color =")).is_err());
+        // Arrays, which the SPIR-V writer can't emit.
+        let array = "type Params = struct {\n  [[offset 0]] values : array<f32, 4>;\n};";
+        assert!(compile_wgsl(&format!("{}{}", array, source)).is_err());
+    }
+}
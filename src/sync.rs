@@ -0,0 +1,243 @@
+use crate::texture::WindowLevel;
+use std::{cell::RefCell, rc::Rc};
+
+/// Properties that can be synchronized, combine them with `|`.
+pub const SYNC_PAN: u32 = 1;
+pub const SYNC_ZOOM: u32 = 2;
+pub const SYNC_WINDOW: u32 = 4;
+pub const SYNC_FRAME: u32 = 8;
+
+/// A change of the view in one controller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewChange {
+    /// A pan, both in screen pixels and in image pixels.
+    Pan {
+        screen: (f32, f32),
+        image: (f32, f32),
+    },
+    /// The magnification was multiplied by the factor.
+    Zoom(f32),
+    /// The window of raw values, see `WindowLevel`.
+    Window { center: f32, width: f32 },
+    /// The frame shown of a sequence.
+    Frame(usize),
+}
+
+impl ViewChange {
+    fn property(&self) -> u32 {
+        match self {
+            ViewChange::Pan { .. } => SYNC_PAN,
+            ViewChange::Zoom(_) => SYNC_ZOOM,
+            ViewChange::Window { .. } => SYNC_WINDOW,
+            ViewChange::Frame(_) => SYNC_FRAME,
+        }
+    }
+}
+
+/// The synchronized properties of a view at one point in time. Changes that
+/// aren't made by dragging, e.g. transitions, inertia, undo and playback, are
+/// published as the difference between two snapshots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewSnapshot {
+    /// The pan in screen pixels.
+    pub position: (f32, f32),
+    /// Screen pixels per image pixel.
+    pub scale: f32,
+    pub window: Option<WindowLevel>,
+    pub frame: Option<usize>,
+}
+
+impl ViewSnapshot {
+    /// The changes that lead from this snapshot to `after`.
+    pub fn changes_to(&self, after: &ViewSnapshot) -> Vec<ViewChange> {
+        let mut changes = Vec::new();
+        let screen = (
+            after.position.0 - self.position.0,
+            after.position.1 - self.position.1,
+        );
+        if screen != (0.0, 0.0) {
+            changes.push(ViewChange::Pan {
+                screen,
+                image: (screen.0 / after.scale, screen.1 / after.scale),
+            });
+        }
+        if after.scale != self.scale && self.scale > 0.0 {
+            changes.push(ViewChange::Zoom(after.scale / self.scale));
+        }
+        if after.frame != self.frame {
+            changes.extend(after.frame.map(ViewChange::Frame));
+        }
+        if after.window != self.window {
+            changes.extend(after.window.map(|window| ViewChange::Window {
+                center: window.center,
+                width: window.width,
+            }));
+        }
+        changes
+    }
+}
+
+/// How a pan is replayed in the other members of a group.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncSpace {
+    /// Move the same number of screen pixels.
+    Screen,
+    /// Move the same number of image pixels.
+    Image,
+}
+
+struct Member {
+    id: usize,
+    properties: u32,
+    inbox: Vec<ViewChange>,
+}
+
+#[derive(Default)]
+struct Group {
+    next_id: usize,
+    members: Vec<Member>,
+}
+
+/// A group of views that follow each other. Changes are queued per member
+/// and applied when the member polls the group.
+#[derive(Clone, Default)]
+pub struct SyncGroup {
+    group: Rc<RefCell<Group>>,
+}
+
+impl SyncGroup {
+    pub fn new() -> Self {
+        SyncGroup::default()
+    }
+
+    /// Join the group, receiving changes of the given properties.
+    pub fn join(&self, properties: u32, space: SyncSpace) -> SyncMember {
+        let mut group = self.group.borrow_mut();
+        let id = group.next_id;
+        group.next_id += 1;
+        group.members.push(Member {
+            id,
+            properties,
+            inbox: Vec::new(),
+        });
+        SyncMember {
+            group: self.clone(),
+            id,
+            properties,
+            space,
+        }
+    }
+}
+
+/// The membership of one view in a `SyncGroup`, leaves the group when dropped.
+pub struct SyncMember {
+    group: SyncGroup,
+    id: usize,
+    properties: u32,
+    pub space: SyncSpace,
+}
+
+impl SyncMember {
+    /// Send a change to the other members that want it.
+    pub fn publish(&self, change: ViewChange) {
+        if self.properties & change.property() == 0 {
+            return;
+        }
+        let mut group = self.group.group.borrow_mut();
+        group
+            .members
+            .iter_mut()
+            .filter(|member| member.id != self.id && member.properties & change.property() != 0)
+            .for_each(|member| member.inbox.push(change));
+    }
+
+    /// Take the changes published by the other members since the last poll.
+    pub fn poll(&self) -> Vec<ViewChange> {
+        let mut group = self.group.group.borrow_mut();
+        group
+            .members
+            .iter_mut()
+            .find(|member| member.id == self.id)
+            .map(|member| std::mem::take(&mut member.inbox))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for SyncMember {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.group
+            .group
+            .borrow_mut()
+            .members
+            .retain(|member| member.id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_reach_other_members() {
+        let group = SyncGroup::new();
+        let a = group.join(SYNC_PAN | SYNC_ZOOM, SyncSpace::Screen);
+        let b = group.join(SYNC_PAN | SYNC_ZOOM, SyncSpace::Image);
+        let c = group.join(SYNC_ZOOM, SyncSpace::Screen);
+
+        let pan = ViewChange::Pan {
+            screen: (2.0, 0.0),
+            image: (1.0, 0.0),
+        };
+        a.publish(pan);
+        a.publish(ViewChange::Zoom(2.0));
+
+        assert!(a.poll().is_empty());
+        assert_eq!(b.poll(), vec![pan, ViewChange::Zoom(2.0)]);
+        assert_eq!(c.poll(), vec![ViewChange::Zoom(2.0)]);
+        assert!(b.poll().is_empty());
+
+        drop(b);
+        a.publish(ViewChange::Zoom(0.5));
+        assert_eq!(c.poll(), vec![ViewChange::Zoom(0.5)]);
+    }
+
+    #[test]
+    fn snapshot_changes() {
+        let before = ViewSnapshot {
+            position: (10.0, 0.0),
+            scale: 2.0,
+            window: Some(WindowLevel {
+                center: 100.0,
+                width: 50.0,
+            }),
+            frame: Some(0),
+        };
+        assert!(before.changes_to(&before).is_empty());
+
+        let after = ViewSnapshot {
+            position: (14.0, -2.0),
+            scale: 4.0,
+            window: Some(WindowLevel {
+                center: 200.0,
+                width: -50.0,
+            }),
+            frame: Some(3),
+        };
+        assert_eq!(
+            before.changes_to(&after),
+            vec![
+                ViewChange::Pan {
+                    screen: (4.0, -2.0),
+                    image: (1.0, -0.5),
+                },
+                ViewChange::Zoom(2.0),
+                ViewChange::Frame(3),
+                ViewChange::Window {
+                    center: 200.0,
+                    width: -50.0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use crate::frames::Frame;
+use crate::raw::RawImage;
+use std::mem;
+
+/// RGBA samples as f32, row by row.
+#[derive(Debug, Clone)]
+pub struct FloatImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl FloatImage {
+    /// An opaque black image, an error if the pixels don't fit in memory.
+    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or_else(|| format!("Image of {}x{} pixels is too large", width, height))?;
+        Ok(FloatImage {
+            width,
+            height,
+            pixels: vec![[0.0, 0.0, 0.0, 1.0]; count],
+        })
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<[f32; 4]> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[self.index(x, y)])
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: [f32; 4]) {
+        if x < self.width && y < self.height {
+            let index = self.index(x, y);
+            self.pixels[index] = value;
+        }
+    }
+}
+
+/// The values of an image, kept on the CPU for probing.
+pub enum Pixels {
+    /// sRGB encoded.
+    Rgba8(image::RgbaImage),
+    /// Linear, or raw values.
+    Float(FloatImage),
+    /// Headerless pixels, converted on the GPU.
+    Raw(RawImage),
+}
+
+/// Maps raw values to the display: `center - width / 2` is shown as black and
+/// `center + width / 2` as white. A negative width inverts, as for DICOM
+/// MONOCHROME1 images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowLevel {
+    pub center: f32,
+    pub width: f32,
+}
+
+impl WindowLevel {
+    /// The window spanning the values of an image.
+    pub fn from_range(min: f32, max: f32) -> Self {
+        WindowLevel {
+            center: (min + max) / 2.0,
+            width: (max - min).max(f32::EPSILON),
+        }
+    }
+
+    /// The range of the RGB values of an image, None if it has no pixels.
+    pub fn of_image(image: &FloatImage) -> Option<Self> {
+        let mut values = image.pixels.iter().flat_map(|p| p[..3].iter().copied());
+        let first = values.next()?;
+        let (min, max) = values.fold((first, first), |(min, max), v| (min.min(v), max.max(v)));
+        Some(WindowLevel::from_range(min, max))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ImageUniforms {
+    window_low: f32,
+    window_width: f32,
+    windowed: u32,
+    float_source: u32,
+}
+unsafe impl bytemuck::Pod for ImageUniforms {}
+unsafe impl bytemuck::Zeroable for ImageUniforms {}
+
+/// An image uploaded to the GPU.
+pub struct ImageTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: (u32, u32),
+    pub pixels: Pixels,
+    /// Rgba32Float textures can't be sampled with linear filtering.
+    pub filterable: bool,
+    /// Raw images are shown through a window, the others as is.
+    window: Option<WindowLevel>,
+    /// Values can be outside 0..1, unlike 8-bit textures.
+    float_source: bool,
+    /// The window the image was loaded with, for resetting.
+    pub default_window: Option<WindowLevel>,
+    /// Width and height of the pixels in millimeters, for measurements.
+    pub pixel_spacing: Option<(f32, f32)>,
+    /// ImageUniforms with the window, bound next to the texture.
+    pub uniform_buffer: wgpu::Buffer,
+    /// The format of textures written from the CPU, None for the ones
+    /// rendered by `RawConverter`.
+    upload_format: Option<wgpu::TextureFormat>,
+}
+
+// The largest finite half float.
+const HALF_MAX: f32 = 65504.0;
+
+impl ImageTexture {
+    /// The colors are taken to be sRGB encoded, the shaders sample linear values.
+    pub fn from_rgba(device: &wgpu::Device, queue: &wgpu::Queue, image: image::RgbaImage) -> Self {
+        let size = image.dimensions();
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let (texture, view) = Self::create(device, size, format);
+        Self::write(queue, &texture, size, 4, &image);
+        Self::new(
+            device,
+            queue,
+            (texture, view),
+            Pixels::Rgba8(image),
+            Some(format),
+            true,
+            None,
+        )
+    }
+
+    /// Upload as Rgba16Float, or as Rgba32Float if the values don't fit in half floats.
+    pub fn from_float(device: &wgpu::Device, queue: &wgpu::Queue, image: FloatImage) -> Self {
+        let size = (image.width, image.height);
+        let format = Self::float_format(&image, false);
+        let (texture, view) = Self::create(device, size, format);
+        Self::write_float(queue, &texture, format, &image);
+        Self::new(
+            device,
+            queue,
+            (texture, view),
+            Pixels::Float(image),
+            Some(format),
+            // Rgba32Float textures can't be filtered.
+            format == wgpu::TextureFormat::Rgba16Float,
+            None,
+        )
+    }
+
+    /// Upload raw values, e.g. 16-bit counts, shown through a window. They are
+    /// kept as Rgba32Float since half floats only hold integers up to 2048.
+    pub fn from_raw(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: FloatImage,
+        window: WindowLevel,
+    ) -> Self {
+        let size = (image.width, image.height);
+        let format = Self::float_format(&image, true);
+        let (texture, view) = Self::create(device, size, format);
+        Self::write_float(queue, &texture, format, &image);
+        Self::new(
+            device,
+            queue,
+            (texture, view),
+            Pixels::Float(image),
+            Some(format),
+            false,
+            Some(window),
+        )
+    }
+
+    /// A texture converted from a raw image by `RawConverter`. 16-bit formats
+    /// are shown through a window over the range of their values.
+    pub fn from_converted(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: (wgpu::Texture, wgpu::TextureView),
+        image: RawImage,
+    ) -> Self {
+        let raw = image.format.is_raw();
+        let window = if raw {
+            let (min, max) = image.range();
+            Some(WindowLevel::from_range(min, max))
+        } else {
+            None
+        };
+        Self::new(
+            device,
+            queue,
+            textures,
+            Pixels::Raw(image),
+            None,
+            !raw,
+            window,
+        )
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        (texture, view): (wgpu::Texture, wgpu::TextureView),
+        pixels: Pixels,
+        upload_format: Option<wgpu::TextureFormat>,
+        filterable: bool,
+        window: Option<WindowLevel>,
+    ) -> Self {
+        let size = match &pixels {
+            Pixels::Rgba8(image) => image.dimensions(),
+            Pixels::Float(image) => (image.width, image.height),
+            Pixels::Raw(image) => (image.width, image.height),
+        };
+        let float_source = match &pixels {
+            Pixels::Rgba8(_) => false,
+            Pixels::Float(_) => true,
+            Pixels::Raw(image) => image.format.is_raw(),
+        };
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ImageUniforms"),
+            size: mem::size_of::<ImageUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let mut texture = ImageTexture {
+            texture,
+            view,
+            size,
+            pixels,
+            filterable,
+            window: None,
+            float_source,
+            default_window: window,
+            pixel_spacing: None,
+            uniform_buffer,
+            upload_format,
+        };
+        texture.set_window(queue, window);
+        texture
+    }
+
+    pub fn window(&self) -> Option<WindowLevel> {
+        self.window
+    }
+
+    /// Change the window of a raw image, None shows the values as is.
+    pub fn set_window(&mut self, queue: &wgpu::Queue, window: Option<WindowLevel>) {
+        self.window = window;
+        let uniforms = match window {
+            Some(window) => ImageUniforms {
+                window_low: window.center - window.width / 2.0,
+                window_width: if window.width.abs() < f32::EPSILON {
+                    f32::EPSILON
+                } else {
+                    window.width
+                },
+                windowed: 1,
+                float_source: self.float_source as u32,
+            },
+            None => ImageUniforms {
+                window_low: 0.0,
+                window_width: 1.0,
+                windowed: 0,
+                float_source: self.float_source as u32,
+            },
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Show another frame of the same size and format, written into this
+    /// texture so its views and the bind groups using them stay valid. The
+    /// window is left as is. Frames that don't fit are given back.
+    pub fn write_frame(&mut self, queue: &wgpu::Queue, frame: Frame) -> Result<(), Frame> {
+        let format = match &frame.pixels {
+            Pixels::Rgba8(image) if image.dimensions() == self.size => {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            }
+            Pixels::Float(image) if (image.width, image.height) == self.size => {
+                Self::float_format(image, frame.window.is_some())
+            }
+            _ => return Err(frame),
+        };
+        if self.upload_format != Some(format) {
+            return Err(frame);
+        }
+        match &frame.pixels {
+            Pixels::Rgba8(image) => Self::write(queue, &self.texture, self.size, 4, image),
+            Pixels::Float(image) => Self::write_float(queue, &self.texture, format, image),
+            Pixels::Raw(_) => unreachable!(),
+        }
+        self.pixels = frame.pixels;
+        self.default_window = frame.window;
+        self.pixel_spacing = frame.pixel_spacing;
+        Ok(())
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST;
+        Self::create_with_usage(device, size, format, usage)
+    }
+
+    /// A texture that is rendered to instead of written, see `from_converted`.
+    pub fn create_attachment(
+        device: &wgpu::Device,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+        Self::create_with_usage(device, size, format, usage)
+    }
+
+    fn create_with_usage(
+        device: &wgpu::Device,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsage,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            label: Some("ImageTexture"),
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            format,
+            dimension: wgpu::TextureViewDimension::D2,
+            aspect: wgpu::TextureAspect::default(),
+            base_mip_level: 0,
+            base_array_layer: 0,
+            level_count: 1,
+            array_layer_count: 1,
+        });
+        (texture, view)
+    }
+
+    // Rgba16Float if the values fit in half floats, raw values are always Rgba32Float.
+    fn float_format(image: &FloatImage, raw: bool) -> wgpu::TextureFormat {
+        let fits_half = || {
+            image
+                .pixels
+                .iter()
+                .flat_map(|p| p.iter())
+                .all(|v| v.abs() <= HALF_MAX)
+        };
+        if !raw && fits_half() {
+            wgpu::TextureFormat::Rgba16Float
+        } else {
+            wgpu::TextureFormat::Rgba32Float
+        }
+    }
+
+    fn write_float(
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        image: &FloatImage,
+    ) {
+        let size = (image.width, image.height);
+        if format == wgpu::TextureFormat::Rgba16Float {
+            let halfs: Vec<u16> = image
+                .pixels
+                .iter()
+                .flat_map(|p| p.iter())
+                .map(|&v| half::f16::from_f32(v).to_bits())
+                .collect();
+            Self::write(queue, texture, size, 8, bytemuck::cast_slice(&halfs));
+        } else {
+            Self::write(
+                queue,
+                texture,
+                size,
+                16,
+                bytemuck::cast_slice(&image.pixels),
+            );
+        }
+    }
+
+    // Queue the copy of the texture data
+    fn write(
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        size: (u32, u32),
+        bytes_per_pixel: u32,
+        data: &[u8],
+    ) {
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: bytes_per_pixel * size.0,
+                rows_per_image: size.1,
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth: 1,
+            },
+        );
+    }
+
+    pub fn size_f32(&self) -> (f32, f32) {
+        (self.size.0 as f32, self.size.1 as f32)
+    }
+
+    /// The value of the pixel at (x, y): 8-bit images in 0..1 as stored, float
+    /// and raw images as loaded, see `RawImage::pixel` for raw buffers.
+    pub fn probe(&self, x: u32, y: u32) -> Option<[f32; 4]> {
+        match &self.pixels {
+            Pixels::Rgba8(image) if x < self.size.0 && y < self.size.1 => {
+                let p = image.get_pixel(x, y).0;
+                Some([
+                    p[0] as f32 / 255.0,
+                    p[1] as f32 / 255.0,
+                    p[2] as f32 / 255.0,
+                    p[3] as f32 / 255.0,
+                ])
+            }
+            Pixels::Rgba8(_) => None,
+            Pixels::Float(image) => image.get(x, y),
+            Pixels::Raw(image) => image.pixel(x, y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_of_image() {
+        let mut image = FloatImage::new(2, 1).unwrap();
+        image.set(0, 0, [100.0, 200.0, 300.0, 1.0]);
+        image.set(1, 0, [400.0, 500.0, 1100.0, 1.0]);
+        let window = WindowLevel::of_image(&image).unwrap();
+        assert_eq!(window.center, 600.0);
+        assert_eq!(window.width, 1000.0);
+        assert!(WindowLevel::of_image(&FloatImage::new(0, 0).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::frames::{Frame, FrameSource};
+use crate::texture::{FloatImage, Pixels, WindowLevel};
+use ::tiff::decoder::{Decoder, DecodingResult};
+use ::tiff::ColorType;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+/// Whether the bytes are a little or big endian TIFF file.
+pub fn is_tiff(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*")
+}
+
+/// The pages of a TIFF file as frames, decoded when asked for. 8-bit pages
+/// are shown as is. 16-bit, 32-bit and float pages keep their raw values and
+/// are shown through a window spanning the values of the page.
+pub struct TiffFrames {
+    bytes: Vec<u8>,
+    // Where the directory of each page starts, so a page is decoded without
+    // reading the pages before it.
+    ifd_offsets: Vec<u64>,
+}
+
+fn tiff_error(e: ::tiff::TiffError) -> String {
+    format!("Failed to read TIFF: {}", e)
+}
+
+impl TiffFrames {
+    pub fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        let mut decoder = Decoder::new(Cursor::new(&bytes[..])).map_err(tiff_error)?;
+        while decoder.more_images() {
+            decoder.next_image().map_err(tiff_error)?;
+        }
+        let ifd_offsets = ifd_offsets(&bytes).ok_or("Invalid TIFF directory offsets")?;
+        Ok(TiffFrames { bytes, ifd_offsets })
+    }
+
+    // The file as read by a decoder that starts at the page.
+    fn page(&self, index: usize) -> PageReader<'_> {
+        let little = self.bytes.starts_with(b"II");
+        // 43 instead of 42 for BigTIFF, with 64-bit offsets
+        let big = self.bytes[if little { 2 } else { 3 }] == 43;
+        let mut header = self.bytes[..if big { 16 } else { 8 }].to_vec();
+        let field = if big { 8..16 } else { 4..8 };
+        let len = field.len();
+        let offset = self.ifd_offsets[index].to_le_bytes();
+        for (i, b) in header[field].iter_mut().enumerate() {
+            *b = offset[if little { i } else { len - 1 - i }];
+        }
+        PageReader {
+            header,
+            bytes: Cursor::new(&self.bytes),
+        }
+    }
+}
+
+// The offsets of the image file directories, None if they run past the end
+// of the file or loop.
+fn ifd_offsets(bytes: &[u8]) -> Option<Vec<u64>> {
+    let little = bytes.starts_with(b"II");
+    let read = |pos: u64, len: usize| -> Option<u64> {
+        let pos = usize::try_from(pos).ok()?;
+        let field = bytes.get(pos..pos.checked_add(len)?)?;
+        Some(field.iter().enumerate().fold(0, |value, (i, &b)| {
+            let shift = if little { i } else { len - 1 - i };
+            value | (b as u64) << (8 * shift)
+        }))
+    };
+    let big = read(2, 2)? == 43;
+    let mut offsets = Vec::new();
+    let mut seen = HashSet::new();
+    let mut next = if big { read(8, 8)? } else { read(4, 4)? };
+    while next != 0 {
+        if !seen.insert(next) {
+            return None;
+        }
+        offsets.push(next);
+        next = if big {
+            let entries = read(next, 8)?;
+            let end = next.checked_add(8)?.checked_add(entries.checked_mul(20)?)?;
+            read(end, 8)?
+        } else {
+            let entries = read(next, 2)?;
+            read(next + 2 + 12 * entries, 4)?
+        };
+    }
+    Some(offsets)
+}
+
+// Reads the file with the offset of the first directory in the header
+// replaced, the decoder then takes that page for the first one.
+struct PageReader<'a> {
+    header: Vec<u8>,
+    bytes: Cursor<&'a Vec<u8>>,
+}
+
+impl Read for PageReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.bytes.position() as usize;
+        if pos >= self.header.len() {
+            return self.bytes.read(buf);
+        }
+        let len = buf.len().min(self.header.len() - pos);
+        buf[..len].copy_from_slice(&self.header[pos..pos + len]);
+        self.bytes.set_position((pos + len) as u64);
+        Ok(len)
+    }
+}
+
+impl Seek for PageReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.bytes.seek(pos)
+    }
+}
+
+impl FrameSource for TiffFrames {
+    fn frame_count(&self) -> usize {
+        self.ifd_offsets.len()
+    }
+
+    fn frame(&mut self, index: usize) -> Result<Frame, String> {
+        if index >= self.ifd_offsets.len() {
+            return Err(format!(
+                "No page {} in TIFF of {} pages",
+                index,
+                self.ifd_offsets.len()
+            ));
+        }
+        let mut decoder = Decoder::new(self.page(index)).map_err(tiff_error)?;
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid TIFF page size: {}x{}", width, height));
+        }
+        let channels = match decoder.colortype().map_err(tiff_error)? {
+            ColorType::Gray(_) => 1,
+            ColorType::GrayA(_) => 2,
+            ColorType::RGB(_) => 3,
+            ColorType::RGBA(_) => 4,
+            other => return Err(format!("Unsupported TIFF color type: {:?}", other)),
+        };
+        let data = decoder.read_image().map_err(tiff_error)?;
+        let is_u8 = matches!(data, DecodingResult::U8(_));
+        let pixels = match data {
+            DecodingResult::U8(samples) => {
+                to_rgba(samples.into_iter().map(f32::from), channels, 255.0)
+            }
+            DecodingResult::U16(samples) => {
+                to_rgba(samples.into_iter().map(f32::from), channels, 65535.0)
+            }
+            DecodingResult::U32(samples) => to_rgba(
+                samples.into_iter().map(|v| v as f32),
+                channels,
+                4_294_967_295.0,
+            ),
+            DecodingResult::F32(samples) => to_rgba(samples.into_iter(), channels, 1.0),
+            DecodingResult::F64(samples) => {
+                to_rgba(samples.into_iter().map(|v| v as f32), channels, 1.0)
+            }
+            _ => return Err("Unsupported TIFF sample format".to_string()),
+        };
+        if pixels.len() != (width * height) as usize {
+            return Err("Truncated TIFF page".to_string());
+        }
+        if is_u8 {
+            let image = image::RgbaImage::from_fn(width, height, |x, y| {
+                let p = pixels[(y * width + x) as usize];
+                image::Rgba([p[0] as u8, p[1] as u8, p[2] as u8, (p[3] * 255.0) as u8])
+            });
+            return Ok(Frame {
+                pixels: Pixels::Rgba8(image),
+                window: None,
+                pixel_spacing: None,
+            });
+        }
+        let image = FloatImage {
+            width,
+            height,
+            pixels,
+        };
+        let window = WindowLevel::of_image(&image);
+        Ok(Frame {
+            pixels: Pixels::Float(image),
+            window,
+            pixel_spacing: None,
+        })
+    }
+}
+
+// Gray is replicated to RGB, the alpha samples are divided by `alpha_max`
+// and alpha is 1 if missing.
+fn to_rgba(samples: impl Iterator<Item = f32>, channels: usize, alpha_max: f32) -> Vec<[f32; 4]> {
+    let samples: Vec<f32> = samples.collect();
+    samples
+        .chunks_exact(channels)
+        .map(|s| match *s {
+            [v] => [v, v, v, 1.0],
+            [v, a] => [v, v, v, a / alpha_max],
+            [r, g, b] => [r, g, b, 1.0],
+            [r, g, b, a, ..] => [r, g, b, a / alpha_max],
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A little endian TIFF with uncompressed 16-bit gray pages of 2x1 pixels.
+    fn gray16_stack(pages: &[[u16; 2]]) -> Vec<u8> {
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        for (i, page) in pages.iter().enumerate() {
+            let ifd = bytes.len() as u32;
+            let entries = 8u16;
+            let ifd_size = 2 + 12 * entries as u32 + 4;
+            let data = ifd + ifd_size;
+            let next = if i + 1 < pages.len() { data + 4 } else { 0 };
+            bytes.extend_from_slice(&entries.to_le_bytes());
+            let mut entry = |tag: u16, kind: u16, value: u32| {
+                bytes.extend_from_slice(&tag.to_le_bytes());
+                bytes.extend_from_slice(&kind.to_le_bytes());
+                bytes.extend_from_slice(&1u32.to_le_bytes());
+                bytes.extend_from_slice(&value.to_le_bytes());
+            };
+            // Width, height, bits per sample, compression, photometric,
+            // strip offsets, rows per strip and strip byte counts.
+            entry(256, 4, 2);
+            entry(257, 4, 1);
+            entry(258, 3, 16);
+            entry(259, 3, 1);
+            entry(262, 3, 1);
+            entry(273, 4, data);
+            entry(278, 4, 1);
+            entry(279, 4, 4);
+            bytes.extend_from_slice(&next.to_le_bytes());
+            for value in page {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_all_pages_as_raw_values() {
+        let bytes = gray16_stack(&[[0, 1000], [4000, 60000]]);
+        assert!(is_tiff(&bytes));
+        let mut frames = TiffFrames::new(bytes).unwrap();
+        assert_eq!(frames.frame_count(), 2);
+
+        let frame = frames.frame(1).unwrap();
+        match frame.pixels {
+            Pixels::Float(image) => {
+                assert_eq!(image.get(1, 0), Some([60000.0, 60000.0, 60000.0, 1.0]))
+            }
+            _ => panic!("16-bit page decoded as 8-bit"),
+        }
+        assert_eq!(frame.window, Some(WindowLevel::from_range(4000.0, 60000.0)));
+        // Pages are read in any order.
+        let frame = frames.frame(0).unwrap();
+        assert_eq!(frame.window, Some(WindowLevel::from_range(0.0, 1000.0)));
+        assert!(frames.frame(2).is_err());
+    }
+}
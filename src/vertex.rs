@@ -1,6 +1,6 @@
+use crate::view_state::{SavedView, ViewConstraints, ViewState, Zoom, ELASTIC_OVERSCROLL};
 use cgmath::prelude::*;
 use std::mem;
-use crate::view_state::{Zoom, ViewState};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -54,6 +54,58 @@ const VERTICES: &[Vertex] = &[
 
 const INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
 
+/// Uniforms of the vertex shader, one set per drawn quad.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TransformUniforms {
+    unit_to_clip: [[f32; 4]; 4],
+    tex_scale: [f32; 2],
+    _padding: [f32; 2],
+}
+unsafe impl bytemuck::Pod for TransformUniforms {}
+unsafe impl bytemuck::Zeroable for TransformUniforms {}
+
+/// A uniform buffer with the transform of one quad and its bind group.
+pub struct TransformBuffer {
+    buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl TransformBuffer {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TransformUniforms"),
+            size: mem::size_of::<TransformUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TransformBindGroup"),
+            layout,
+            bindings: &[wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+            }],
+        });
+        TransformBuffer { buffer, bind_group }
+    }
+
+    /// The layout of the bind group, set 1 of the pipelines.
+    pub fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TransformBindGroupLayout"),
+            bindings: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+            }],
+        })
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, uniforms: TransformUniforms) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+}
+
 pub struct Quad {
     vertices: Vec<Vertex>,
     indexes: Vec<u16>,
@@ -81,19 +133,135 @@ impl Quad {
         quad
     }
 
-    fn compute_image_to_screen(&self, state:&ViewState) -> ViewTransform {
+    /// The number of screen pixels per image pixel for the given view.
+    pub fn image_scale(&self, state: &ViewState) -> f32 {
+        self.zoom_scale(state.zoom)
+    }
+
+    fn fit_scale(&self) -> f32 {
+        self.base_scale(Zoom::Fit(1.0))
+    }
+
+    /// The scale at a magnification of 1 for the kind of zoom.
+    fn base_scale(&self, zoom: Zoom) -> f32 {
+        let x_scale = self.viewport_size.0 / self.image_size.0;
+        let y_scale = self.viewport_size.1 / self.image_size.1;
+        match zoom {
+            Zoom::Fit(_) => x_scale.min(y_scale),
+            Zoom::FitWidth(_) => x_scale,
+            Zoom::FitHeight(_) => y_scale,
+            Zoom::Fill(_) => x_scale.max(y_scale),
+            Zoom::Pixel(_) => 1.0,
+        }
+    }
+
+    /// The number of screen pixels per image pixel for the given zoom.
+    pub fn zoom_scale(&self, zoom: Zoom) -> f32 {
+        self.base_scale(zoom) * zoom.magnification()
+    }
+
+    /// A zoom of the same kind as `like` that gives the scale.
+    pub fn zoom_with_scale(&self, scale: f32, like: Zoom) -> Zoom {
+        like.with_magnification(scale / self.base_scale(like))
+    }
+
+    pub fn image_size(&self) -> (f32, f32) {
+        self.image_size
+    }
 
-        let mut transform = match state.zoom {
-            Zoom::Fit(mag) => {
-                let x_scale = self.viewport_size.0 / self.image_size.0;
-                let y_scale = self.viewport_size.1 / self.image_size.1;
-                let scale = x_scale.min(y_scale) * mag;
-                ViewTransform::scale_diag(scale)
-            },
-            Zoom::Pixel(mag) => {
-                ViewTransform::scale_diag(mag)
-            },
+    pub fn viewport_size(&self) -> (f32, f32) {
+        self.viewport_size
+    }
+
+    /// Enforce the constraints on the view. `before` is the displacement
+    /// before the last change, with `elastic` the view may be panned past the
+    /// limits with resistance.
+    pub fn constrain(
+        &self,
+        state: &mut ViewState,
+        constraints: &ViewConstraints,
+        before: (f32, f32),
+        elastic: bool,
+    ) {
+        let min_scale = self.fit_scale() * constraints.min_magnification;
+        let current = self.image_scale(state);
+        let scale = current
+            .max(min_scale)
+            .min(constraints.max_magnification.max(min_scale));
+        if scale != current {
+            state.set_zoom_mode(self.zoom_with_scale(scale, state.zoom));
+        }
+
+        let slack = if elastic && constraints.elastic {
+            ELASTIC_OVERSCROLL
+        } else {
+            0.0
+        };
+        let axis = |disp: f32, before: f32, viewport: f32, image: f32| {
+            // The image spans [base + disp, base + disp + extent] on screen.
+            let extent = image * scale;
+            let base = (viewport - extent) / 2.0;
+            let visible = constraints.min_visible.max(0.0).min(1.0) * extent.min(viewport);
+            let lo = visible - extent - base;
+            let hi = viewport - visible - base;
+            ViewConstraints::constrain_axis(before, disp, lo, hi, slack * viewport)
         };
+        let disp = state.get_displacement();
+        state.pos = (
+            axis(disp.0, before.0, self.viewport_size.0, self.image_size.0),
+            axis(disp.1, before.1, self.viewport_size.1, self.image_size.1),
+        );
+    }
+
+    /// Describe the view independently of the viewport size.
+    pub fn save_view(&self, state: &ViewState) -> SavedView {
+        let scale = self.image_scale(state);
+        let disp = state.get_displacement();
+        SavedView {
+            zoom: state.zoom,
+            center: (
+                0.5 - disp.0 / (scale * self.image_size.0),
+                0.5 - disp.1 / (scale * self.image_size.1),
+            ),
+        }
+    }
+
+    /// Set the zoom and position of the state from a saved view.
+    pub fn restore_view(&self, saved: &SavedView, state: &mut ViewState) {
+        state.set_zoom_mode(saved.zoom);
+        let scale = self.image_scale(state);
+        state.pos = (
+            (0.5 - saved.center.0) * scale * self.image_size.0,
+            (0.5 - saved.center.1) * scale * self.image_size.1,
+        );
+    }
+
+    /// The image position (in image pixels) under a position in the viewport.
+    pub fn screen_to_image(&self, state: &ViewState, pos: (f32, f32)) -> (f32, f32) {
+        let point = self
+            .compute_image_to_screen(state)
+            .invert()
+            .transform_vertex(&[pos.0, pos.1, 1.0]);
+        (point[0], point[1])
+    }
+
+    /// The part of the image visible in the viewport, as (x, y, width, height)
+    /// in image pixels.
+    pub fn visible_region(&self, state: &ViewState) -> (f32, f32, f32, f32) {
+        let screen_to_image = self.compute_image_to_screen(state).invert();
+        let top_left = screen_to_image.transform_vertex(&[0.0, 0.0, 1.0]);
+        let bottom_right =
+            screen_to_image.transform_vertex(&[self.viewport_size.0, self.viewport_size.1, 1.0]);
+        let clamp = |v: f32, max: f32| v.max(0.0).min(max);
+        let x0 = clamp(top_left[0], self.image_size.0);
+        let y0 = clamp(top_left[1], self.image_size.1);
+        let x1 = clamp(bottom_right[0], self.image_size.0);
+        let y1 = clamp(bottom_right[1], self.image_size.1);
+        (x0, y0, x1 - x0, y1 - y0)
+    }
+
+    fn compute_image_to_screen(&self, state: &ViewState) -> ViewTransform {
+        let mut transform = ViewTransform::scale_diag(self.image_scale(state));
 
         // Always center the image after zoom
         let xform_center = transform.transform_vertex(&[
@@ -115,28 +283,26 @@ impl Quad {
         transform
     }
 
-    pub fn get_vertex(&self, state: &ViewState) -> Vec<Vertex> {
-        //&self.vertices
-        let mut vertex_tranform = self.compute_image_to_screen(state);//self.image_to_screen.clone();
-
-        vertex_tranform.compose_mut(&self.shader_to_screen.invert());
-
-        let vspace = vertex_tranform.transform_vertex(&[1_f32,1_f32,1_f32]);
-        let sspace = vertex_tranform.invert().transform_vertex(&vspace);
-        //log::info!("Transformed: {}, {}", vspace[0], vspace[1]);
-
+    /// The transform from the unit quad to clip space for the vertex shader.
+    pub fn transform_uniforms(&self, state: &ViewState) -> TransformUniforms {
+        let unit_to_clip = ViewTransform::scale(self.image_size.0, self.image_size.1)
+            .compose(&self.compute_image_to_screen(state))
+            .compose(&self.shader_to_screen.invert());
+        TransformUniforms {
+            unit_to_clip: unit_to_clip.to_mat4(),
+            tex_scale: [
+                self.image_size.0 / self.texture_size.0,
+                self.image_size.1 / self.texture_size.1,
+            ],
+            _padding: [0.0; 2],
+        }
+    }
 
-        let v: Vec<_> = self
-            .vertices
-            .iter()
-            .map(|x| Vertex {
-                position: vertex_tranform.transform_vertex(&x.position),
-                tex_coords: x.tex_coords,
-            })
-            .collect();
-        //        dbg!(&v);
-        v
+    /// The vertices of the unit quad, shared by all quads.
+    pub fn vertex_ref(&self) -> &[Vertex] {
+        &self.vertices
     }
+
     pub fn index_ref(&self) -> &[u16] {
         &self.indexes
     }
@@ -149,20 +315,9 @@ impl Quad {
         self.indexes.len() as u32
     }
 
+    /// Set the size of the image and of the texture holding it. The unit quad
+    /// is scaled to the image size by the transform.
     pub fn map_texture_coords(&mut self, img_dims: (f32, f32), tex_dims: (f32, f32)) {
-        let u = img_dims.0 / tex_dims.0;
-        let v = img_dims.1 / tex_dims.1;
-        self.vertices
-            .iter_mut()
-            .zip(VERTICES.iter())
-            .for_each(|(v1, v2)| {
-                v1.position = [
-                    v2.position[0] * img_dims.0,
-                    v2.position[1] * img_dims.1,
-                    v1.position[2],
-                ];
-                v1.tex_coords = [v2.tex_coords[0] * u, v2.tex_coords[1] * v];
-            });
         self.image_size = img_dims;
         self.texture_size = tex_dims;
     }
@@ -252,6 +407,17 @@ impl ViewTransform {
         ViewTransform { mat }
     }
 
+    /// As a 4x4 column-major matrix acting on (x, y, z, 1), leaving z as is.
+    pub fn to_mat4(&self) -> [[f32; 4]; 4] {
+        let m = &self.mat;
+        [
+            [m.x.x, m.x.y, 0.0, 0.0],
+            [m.y.x, m.y.y, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [m.z.x, m.z.y, 0.0, 1.0],
+        ]
+    }
+
     pub fn transform_vertex(&self, v: &[f32; 3]) -> [f32; 3] {
         // Set z = 1.0 to be affected by translations
         let mut v = cgmath::vec3(v[0], v[1], 1.0);
@@ -282,7 +448,101 @@ mod tests {
         let mut q = Quad::new();
         q.set_viewport_size((512_f32, 512_f32));
         q.map_texture_coords((512_f32, 512_f32), (1024_f32, 1024_f32));
-        let v = q.get_vertex(&state);
-        dbg!(v);
+        let uniforms = q.transform_uniforms(&state);
+        // The image fills the viewport moved up and left by half its size, in
+        // clip space from (-2, 2) to (0, 0).
+        let m = uniforms.unit_to_clip;
+        let corners = [m[3][0], m[3][1], m[0][0] + m[3][0], m[1][1] + m[3][1]];
+        let expected = [-2.0, 2.0, 0.0, 0.0];
+        assert!(corners
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-5));
+        assert_eq!(uniforms.tex_scale, [0.5, 0.5]);
+    }
+
+    #[test]
+    fn constrain_keeps_image_visible() {
+        let constraints = ViewConstraints {
+            min_magnification: 0.5,
+            max_magnification: 4.0,
+            min_visible: 1.0,
+            elastic: false,
+        };
+        let mut q = Quad::new();
+        q.set_viewport_size((512_f32, 512_f32));
+        q.map_texture_coords((128_f32, 128_f32), (128_f32, 128_f32));
+
+        let mut state = ViewState::new();
+        state.update_magnification(0.1);
+        state.pos = (1000.0, -1000.0);
+        q.constrain(&mut state, &constraints, (0.0, 0.0), false);
+        assert_eq!(q.image_scale(&state), 2.0);
+        // A 256 pixel image in a 512 pixel viewport can move 128 pixels from the center.
+        assert_eq!(state.get_displacement(), (128.0, -128.0));
+
+        state.update_magnification(100.0);
+        q.constrain(&mut state, &constraints, (0.0, 0.0), false);
+        assert_eq!(q.image_scale(&state), 4.0);
+        assert_eq!(state.get_displacement(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn zoom_modes() {
+        let mut q = Quad::new();
+        q.set_viewport_size((400_f32, 300_f32));
+        q.map_texture_coords((100_f32, 200_f32), (100_f32, 200_f32));
+        assert_eq!(q.zoom_scale(Zoom::Fit(1.0)), 1.5);
+        assert_eq!(q.zoom_scale(Zoom::FitWidth(1.0)), 4.0);
+        assert_eq!(q.zoom_scale(Zoom::FitHeight(2.0)), 3.0);
+        assert_eq!(q.zoom_scale(Zoom::Fill(1.0)), 4.0);
+        assert_eq!(q.zoom_scale(Zoom::Pixel(1.0)), 1.0);
+        assert_eq!(q.zoom_with_scale(3.0, Zoom::Fit(1.0)), Zoom::Fit(2.0));
+    }
+
+    #[test]
+    fn visible_region() {
+        let mut q = Quad::new();
+        q.set_viewport_size((400_f32, 300_f32));
+        q.map_texture_coords((100_f32, 200_f32), (100_f32, 200_f32));
+        let close = |a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)| {
+            [a.0 - b.0, a.1 - b.1, a.2 - b.2, a.3 - b.3]
+                .iter()
+                .all(|d| d.abs() < 1e-4)
+        };
+        let mut state = ViewState::new();
+        assert!(close(q.visible_region(&state), (0.0, 0.0, 100.0, 200.0)));
+
+        // 200 x 150 image pixels around the center
+        state.set_zoom_mode(Zoom::Pixel(2.0));
+        assert!(close(q.visible_region(&state), (0.0, 25.0, 100.0, 150.0)));
+    }
+
+    #[test]
+    fn saved_view_survives_resize() {
+        let mut state = ViewState::new();
+        state.update_magnification(2.0);
+        state.pos = (100.0, -50.0);
+        let mut q = Quad::new();
+        q.set_viewport_size((512_f32, 512_f32));
+        q.map_texture_coords((256_f32, 128_f32), (256_f32, 128_f32));
+
+        let saved = q.save_view(&state);
+        let center = q
+            .compute_image_to_screen(&state)
+            .invert()
+            .transform_vertex(&[256.0, 256.0, 1.0]);
+        assert!((saved.center.0 - center[0] / 256.0).abs() < 1e-5);
+        assert!((saved.center.1 - center[1] / 128.0).abs() < 1e-5);
+
+        // The same image point stays in the center after a resize.
+        q.set_viewport_size((1024_f32, 300_f32));
+        q.restore_view(&saved, &mut state);
+        let center = q
+            .compute_image_to_screen(&state)
+            .invert()
+            .transform_vertex(&[512.0, 150.0, 1.0]);
+        assert!((saved.center.0 - center[0] / 256.0).abs() < 1e-5);
+        assert!((saved.center.1 - center[1] / 128.0).abs() < 1e-5);
     }
 }
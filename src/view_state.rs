@@ -1,16 +1,124 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
 pub struct ViewState {
     pub zoom: Zoom,
     pub pos: (f32, f32),
     pub anchor: Option<(f32, f32)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Zoom {
+    /// Relative to fitting the whole image in the viewport.
     Fit(f32),
+    /// Relative to fitting the image width in the viewport.
+    FitWidth(f32),
+    /// Relative to fitting the image height in the viewport.
+    FitHeight(f32),
+    /// Relative to covering the whole viewport with the image.
+    Fill(f32),
+    /// Screen pixels per image pixel.
     Pixel(f32),
 }
 
+impl Zoom {
+    pub fn from_name(name: &str, mag: f32) -> Option<Zoom> {
+        match name {
+            "fit" => Some(Zoom::Fit(mag)),
+            "fit_width" => Some(Zoom::FitWidth(mag)),
+            "fit_height" => Some(Zoom::FitHeight(mag)),
+            "fill" => Some(Zoom::Fill(mag)),
+            "pixel" => Some(Zoom::Pixel(mag)),
+            _ => None,
+        }
+    }
+
+    pub fn magnification(&self) -> f32 {
+        match *self {
+            Zoom::Fit(mag)
+            | Zoom::FitWidth(mag)
+            | Zoom::FitHeight(mag)
+            | Zoom::Fill(mag)
+            | Zoom::Pixel(mag) => mag,
+        }
+    }
+
+    /// The same kind of zoom with another magnification.
+    pub fn with_magnification(&self, mag: f32) -> Zoom {
+        match self {
+            Zoom::Fit(_) => Zoom::Fit(mag),
+            Zoom::FitWidth(_) => Zoom::FitWidth(mag),
+            Zoom::FitHeight(_) => Zoom::FitHeight(mag),
+            Zoom::Fill(_) => Zoom::Fill(mag),
+            Zoom::Pixel(_) => Zoom::Pixel(mag),
+        }
+    }
+}
+
+/// A view described independently of the viewport size, so it can be
+/// stored and restored on a canvas of a different size.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavedView {
+    pub zoom: Zoom,
+    /// The point of the image in the center of the viewport, relative to
+    /// the image size, i.e. (0.5, 0.5) is the center of the image.
+    pub center: (f32, f32),
+}
+
+/// Limits on how far the view can be zoomed and panned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewConstraints {
+    /// Smallest magnification, relative to fitting the image in the viewport.
+    pub min_magnification: f32,
+    /// Largest magnification, in screen pixels per image pixel.
+    pub max_magnification: f32,
+    /// Fraction of the image (or of the viewport, if the image is larger)
+    /// that must stay visible along each axis.
+    pub min_visible: f32,
+    /// Allow panning past the limits with increasing resistance while dragging.
+    pub elastic: bool,
+}
+
+// How far past the limits an elastic pan can go, relative to the viewport size.
+pub const ELASTIC_OVERSCROLL: f32 = 0.15;
+
+impl ViewConstraints {
+    pub fn new() -> Self {
+        ViewConstraints {
+            min_magnification: 0.1,
+            max_magnification: 64.0,
+            min_visible: 0.25,
+            elastic: true,
+        }
+    }
+
+    /// Constrain a displacement along one axis to [lo, hi]. With a slack the
+    /// displacement may move up to slack past the range, where each step
+    /// outwards is damped more the further out it is.
+    pub fn constrain_axis(before: f32, after: f32, lo: f32, hi: f32, slack: f32) -> f32 {
+        let clamped = after.max(lo).min(hi);
+        if slack <= 0.0 {
+            return clamped;
+        }
+        let excess_before = (before - before.max(lo).min(hi)).abs();
+        let excess_after = (after - clamped).abs();
+        if excess_after <= excess_before {
+            // Moving back towards the allowed range is not damped.
+            return after;
+        }
+        let resistance = 0.5 * (1.0 - excess_before / slack).max(0.0);
+        let excess = (excess_before + (excess_after - excess_before) * resistance).min(slack);
+        clamped + excess * (after - clamped).signum()
+    }
+}
+
+impl Default for ViewConstraints {
+    fn default() -> Self {
+        ViewConstraints::new()
+    }
+}
+
 impl ViewState {
     pub fn new() -> Self {
         ViewState {
@@ -24,11 +132,14 @@ impl ViewState {
         self.zoom = z;
     }
 
+    pub fn magnification(&self) -> f32 {
+        self.zoom.magnification()
+    }
+
     pub fn update_magnification(&mut self, mag: f32) {
-        self.zoom = match self.zoom {
-            Zoom::Fit(current) => Zoom::Fit(current * mag),
-            Zoom::Pixel(current) => Zoom::Pixel(current * mag),
-        }
+        self.zoom = self
+            .zoom
+            .with_magnification(self.zoom.magnification() * mag);
     }
 
     pub fn set_position(&mut self, pos: (f32, f32)) {
@@ -43,6 +154,10 @@ impl ViewState {
         self.set_anchor(pos);
     }
 
+    pub fn pan_by(&mut self, delta: (f32, f32)) {
+        self.pos = (self.pos.0 + delta.0, self.pos.1 + delta.1);
+    }
+
     pub fn set_zoom(&mut self, pos: (f32, f32)) {
         if self.anchor == None {
             self.set_anchor(pos);
@@ -71,3 +186,41 @@ impl ViewState {
         self.anchor = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constrain_axis() {
+        // Hard limits
+        assert_eq!(
+            ViewConstraints::constrain_axis(0.0, 20.0, -10.0, 10.0, 0.0),
+            10.0
+        );
+        assert_eq!(
+            ViewConstraints::constrain_axis(0.0, -20.0, -10.0, 10.0, 0.0),
+            -10.0
+        );
+        assert_eq!(
+            ViewConstraints::constrain_axis(0.0, 5.0, -10.0, 10.0, 0.0),
+            5.0
+        );
+        // Elastic overscroll is damped
+        assert_eq!(
+            ViewConstraints::constrain_axis(0.0, 20.0, -10.0, 10.0, 40.0),
+            15.0
+        );
+        let further = ViewConstraints::constrain_axis(15.0, 25.0, -10.0, 10.0, 40.0);
+        assert!(further > 15.0 && further < 20.0);
+        assert_eq!(
+            ViewConstraints::constrain_axis(0.0, 1000.0, -10.0, 10.0, 40.0),
+            50.0
+        );
+        // but moving back is not
+        assert_eq!(
+            ViewConstraints::constrain_axis(15.0, 12.0, -10.0, 10.0, 40.0),
+            12.0
+        );
+    }
+}
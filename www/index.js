@@ -4,6 +4,7 @@ let canvas = document.getElementById("foo");
 let old_cursor = null;
 let mouseDown = false;
 let ctrl = false;
+let loupe = false;
 
 canvas.onmousedown = (evt) => {
     if (evt.buttons === 1) {
@@ -16,13 +17,22 @@ canvas.onmouseup = (evt) => {
     mouseDown = false;
     controller.clear_anchor();
     canvas.style.cursor = old_cursor;
+    // Keep rendering while the image glides
+    count = 0;
+    if (animationHandle === null) {
+        doRender();
+    }
 }
 
 canvas.onmousemove = (evt) => {
-    if (mouseDown) {
-        if (evt.ctrlKey) {
+    if (controller === null) {
+        return;
+    }
+    controller.set_loupe_position(evt.offsetX, evt.offsetY);
+    if (mouseDown || loupe) {
+        if (mouseDown && evt.ctrlKey) {
             controller.update_zoom(evt.offsetX, evt.offsetY);
-        } else {
+        } else if (mouseDown) {
             controller.update_position(evt.offsetX, evt.offsetY);
         }
         count = 0;
@@ -32,6 +42,39 @@ canvas.onmousemove = (evt) => {
     }
 }
 
+canvas.onmouseleave = (evt) => {
+    if (controller === null) {
+        return;
+    }
+    controller.hide_loupe();
+    count = 0;
+    if (animationHandle === null) {
+        doRender();
+    }
+}
+
+document.onkeydown = (evt) => {
+    if (controller === null) {
+        return;
+    }
+    if (evt.ctrlKey && evt.key === "z") {
+        controller.undo();
+    } else if (evt.ctrlKey && evt.key === "y") {
+        controller.redo();
+    } else if (evt.key === "r") {
+        controller.reset_view(true);
+    } else if (evt.key === "o") {
+        loupe = !loupe;
+        controller.set_loupe(loupe, 80, 4);
+    } else {
+        return;
+    }
+    count = 0;
+    if (animationHandle === null) {
+        doRender();
+    }
+}
+
 
 let controller = null;
 let animationHandle = null;
@@ -40,7 +83,7 @@ let count = 0;
 function doRender() {
     controller.render();
     count++;
-    if (count < 3) {
+    if (count < 3 || controller.is_animating()) {
         animationHandle = requestAnimationFrame(doRender);
     } else {
         animationHandle = null;
@@ -48,6 +91,9 @@ function doRender() {
 }
 
 async function start() {
+    // Back the canvas with device pixels, the controller renders at the device pixel ratio.
+    canvas.width = Math.round(canvas.clientWidth * window.devicePixelRatio);
+    canvas.height = Math.round(canvas.clientHeight * window.devicePixelRatio);
     controller = await RenderController.new(1, canvas.clientWidth, canvas.clientHeight);
 
     console.log("After");